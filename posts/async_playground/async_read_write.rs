@@ -0,0 +1,386 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::net::{TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::{Duration, Instant};
+
+static WAKE_TIMES: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            let wakers_vec = wake_times.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+static NEW_TASKS: Mutex<Vec<DynFuture>> = Mutex::new(Vec::new());
+
+enum JoinState<T> {
+    Unawaited,
+    Awaited(Waker),
+    Ready(T),
+    Done,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut guard = self.state.lock().unwrap();
+        // Use JoinState::Done as a placeholder, to take ownership of T.
+        match mem::replace(&mut *guard, JoinState::Done) {
+            JoinState::Ready(value) => Poll::Ready(value),
+            JoinState::Unawaited | JoinState::Awaited(_) => {
+                // Replace the previous Waker, if any. We only need the most recent one.
+                *guard = JoinState::Awaited(context.waker().clone());
+                Poll::Pending
+            }
+            JoinState::Done => unreachable!("polled again after Ready"),
+        }
+    }
+}
+
+async fn wrap_with_join_state<F: Future>(future: F, join_state: Arc<Mutex<JoinState<F::Output>>>) {
+    let value = future.await;
+    let mut guard = join_state.lock().unwrap();
+    if let JoinState::Awaited(waker) = &*guard {
+        waker.wake_by_ref();
+    }
+    *guard = JoinState::Ready(value)
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&join_state),
+    };
+    let task = Box::pin(wrap_with_join_state(future, join_state));
+    NEW_TASKS.lock().unwrap().push(task);
+    join_handle
+}
+
+// In production we'd use AtomicBool instead of Mutex<bool>.
+struct AwakeFlag(Mutex<bool>);
+
+impl AwakeFlag {
+    fn check_and_clear(&self) -> bool {
+        let mut guard = self.0.lock().unwrap();
+        let check = *guard;
+        *guard = false;
+        check
+    }
+}
+
+impl Wake for AwakeFlag {
+    fn wake(self: Arc<Self>) {
+        *self.0.lock().unwrap() = true;
+    }
+}
+
+// Unlike the original POLL_FDS, each entry now remembers which direction it cares about, so the
+// main loop can ask libc::poll for exactly POLLIN or POLLOUT instead of always POLLIN. We still
+// don't bother checking `revents` before waking: a spurious wakeup just means a future polls
+// again and re-registers, which is inefficient but allowed.
+static POLL_FDS: Mutex<Vec<(RawFd, libc::c_short, Waker)>> = Mutex::new(Vec::new());
+
+fn register(raw_fd: RawFd, events: libc::c_short, waker: Waker) {
+    POLL_FDS.lock().unwrap().push((raw_fd, events, waker));
+}
+
+// Modeled on futures_io::AsyncRead/AsyncWrite: poll-based, so that a type can suspend without
+// blocking the thread instead of returning a blocking Future by value.
+trait AsyncRead {
+    fn poll_read(&mut self, context: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>>;
+}
+
+trait AsyncWrite {
+    fn poll_write(&mut self, context: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>>;
+}
+
+// Wraps a non-blocking IO type and registers it with the reactor on WouldBlock, instead of
+// assuming (as the old `Copy` future did) that one side of the copy never blocks.
+struct Async<T> {
+    inner: T,
+}
+
+impl<T: Read + AsRawFd> AsyncRead for Async<T> {
+    fn poll_read(&mut self, context: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        match self.inner.read(buf) {
+            Ok(n) => Poll::Ready(Ok(n)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                register(self.inner.as_raw_fd(), libc::POLLIN, context.waker().clone());
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+impl<T: Write + AsRawFd> AsyncWrite for Async<T> {
+    fn poll_write(&mut self, context: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.inner.write(buf) {
+            Ok(n) => Poll::Ready(Ok(n)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                register(self.inner.as_raw_fd(), libc::POLLOUT, context.waker().clone());
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+impl<T: Read + AsRawFd> Async<T> {
+    async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        std::future::poll_fn(|context| self.poll_read(context, buf)).await
+    }
+}
+
+impl<T: Write + AsRawFd> Async<T> {
+    async fn write_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            let n = std::future::poll_fn(|context| self.poll_write(context, buf)).await?;
+            buf = &buf[n..];
+        }
+        Ok(())
+    }
+}
+
+async fn tcp_bind(address: &str) -> io::Result<TcpListener> {
+    // XXX: This is technically blocking. Assume it returns quickly.
+    let listener = TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+async fn tcp_connect(address: &str) -> io::Result<Async<TcpStream>> {
+    // XXX: This is technically blocking. Assume it returns quickly.
+    let socket = TcpStream::connect(address)?;
+    socket.set_nonblocking(true)?;
+    Ok(Async { inner: socket })
+}
+
+struct TcpAccept<'a> {
+    listener: &'a TcpListener,
+}
+
+impl<'a> Future for TcpAccept<'a> {
+    type Output = io::Result<Async<TcpStream>>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<Async<TcpStream>>> {
+        match self.listener.accept() {
+            Ok((stream, _)) => {
+                let result = stream.set_nonblocking(true);
+                Poll::Ready(result.and(Ok(Async { inner: stream })))
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                register(self.listener.as_raw_fd(), libc::POLLIN, context.waker().clone());
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+fn tcp_accept(listener: &TcpListener) -> TcpAccept {
+    TcpAccept { listener }
+}
+
+const COPY_BUF_SIZE: usize = 8 * 1024;
+
+// Unlike the old Copy future, this one buffers bytes itself instead of handing the reader and
+// writer straight to io::copy, so it can suspend on read readiness and write readiness
+// independently: a slow client that stops draining its socket now blocks on POLLOUT instead of
+// silently assuming the write always succeeds.
+struct Copy<'a, R, W> {
+    reader: &'a mut R,
+    writer: &'a mut W,
+    buf: [u8; COPY_BUF_SIZE],
+    pos: usize,
+    cap: usize,
+}
+
+impl<'a, R: AsyncRead, W: AsyncWrite> Future for Copy<'a, R, W> {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.pos == this.cap {
+                match this.reader.poll_read(context, &mut this.buf) {
+                    Poll::Ready(Ok(0)) => return Poll::Ready(Ok(())),
+                    Poll::Ready(Ok(n)) => {
+                        this.pos = 0;
+                        this.cap = n;
+                    }
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+            match this.writer.poll_write(context, &this.buf[this.pos..this.cap]) {
+                Poll::Ready(Ok(n)) => this.pos += n,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+fn copy<'a, R, W>(reader: &'a mut R, writer: &'a mut W) -> Copy<'a, R, W> {
+    Copy {
+        reader,
+        writer,
+        buf: [0; COPY_BUF_SIZE],
+        pos: 0,
+        cap: 0,
+    }
+}
+
+async fn foo_response(n: u64, mut socket: Async<TcpStream>) -> io::Result<()> {
+    let start_msg = format!("start {n}\n");
+    socket.write_all(start_msg.as_bytes()).await?;
+    sleep(Duration::from_secs(1)).await;
+    let end_msg = format!("end {n}\n");
+    socket.write_all(end_msg.as_bytes()).await?;
+    Ok(())
+}
+
+async fn server_main(listener: TcpListener) -> io::Result<()> {
+    let mut n = 1;
+    loop {
+        let socket = tcp_accept(&listener).await?;
+        spawn(async move { foo_response(n, socket).await.unwrap() });
+        n += 1;
+    }
+}
+
+async fn foo_request() -> io::Result<()> {
+    let mut socket = tcp_connect("localhost:8000").await?;
+    let mut stdout = Async {
+        inner: io::stdout(),
+    };
+    copy(&mut socket, &mut stdout).await?;
+    Ok(())
+}
+
+async fn async_main() -> io::Result<()> {
+    // Open the listener here, to avoid racing against the server thread.
+    let listener = tcp_bind("0.0.0.0:8000").await?;
+    spawn(async { server_main(listener).await.unwrap() });
+    let mut task_handles = Vec::new();
+    for _ in 1..=10 {
+        task_handles.push(spawn(foo_request()));
+    }
+    for handle in task_handles {
+        handle.await?;
+    }
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    let awake_flag = Arc::new(AwakeFlag(Mutex::new(false)));
+    let waker = Waker::from(Arc::clone(&awake_flag));
+    let mut context = Context::from_waker(&waker);
+    let mut main_task = Box::pin(async_main());
+    let mut other_tasks: Vec<DynFuture> = Vec::new();
+    loop {
+        // Poll the main task and exit immediately if it's done.
+        if let Poll::Ready(result) = main_task.as_mut().poll(&mut context) {
+            return result;
+        }
+        // Poll other tasks and remove any that are Ready.
+        let is_pending = |task: &mut DynFuture| task.as_mut().poll(&mut context).is_pending();
+        other_tasks.retain_mut(is_pending);
+        // Some tasks might have spawned new tasks. Pop from NEW_TASKS until it's empty. Note that
+        // we can't use while-let here, because that would keep NEW_TASKS locked in the loop body.
+        // See https://fasterthanli.me/articles/a-rust-match-made-in-hell.
+        loop {
+            let Some(mut task) = NEW_TASKS.lock().unwrap().pop() else {
+                break;
+            };
+            // Poll each new task now, instead of waiting for the next iteration of the main loop,
+            // to let them register wakeups. Drop the ones that return Ready. This poll can also
+            // spawn more tasks, so it's important that NEW_TASKS isn't locked here.
+            if task.as_mut().poll(&mut context).is_pending() {
+                other_tasks.push(task);
+            }
+        }
+        // Some tasks might wake other tasks. Re-poll if the AwakeFlag has been set. Polling
+        // futures that aren't ready yet is inefficient but allowed.
+        if awake_flag.check_and_clear() {
+            continue;
+        }
+        // All tasks are either sleeping or blocked on IO. Use libc::poll to wait for IO on any of
+        // the POLL_FDS, now with each fd's own interest (POLLIN or POLLOUT) instead of always
+        // POLLIN. If there are any WAKE_TIMES, use the earliest as a timeout.
+        let mut poll_fds = POLL_FDS.lock().unwrap();
+        let mut poll_structs = Vec::new();
+        for &(raw_fd, events, _) in poll_fds.iter() {
+            poll_structs.push(libc::pollfd {
+                fd: raw_fd,
+                events,
+                revents: 0, // return field, unused
+            });
+        }
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        let timeout_ms = if let Some(time) = wake_times.keys().next() {
+            let duration = time.saturating_duration_since(Instant::now());
+            duration.as_millis() as libc::c_int
+        } else {
+            -1 // infinite timeout
+        };
+        let poll_error_code = unsafe {
+            libc::poll(
+                poll_structs.as_mut_ptr(),
+                poll_structs.len() as libc::nfds_t,
+                timeout_ms,
+            )
+        };
+        if poll_error_code == -1 {
+            panic!("libc::poll failed: {}", io::Error::last_os_error());
+        }
+        // Invoke Wakers from WAKE_TIMES if their time has come.
+        while let Some(entry) = wake_times.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+        // Invoke all Wakers from POLL_FDS. This might wake futures that aren't ready yet, but if
+        // so they'll register another wakeup. It's inefficient but allowed.
+        poll_fds.drain(..).map(|(_, _, waker)| waker).for_each(Waker::wake);
+    }
+}
@@ -0,0 +1,480 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::net::{TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::{Duration, Instant};
+
+static WAKE_TIMES: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            let wakers_vec = wake_times.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+static NEW_TASKS: Mutex<Vec<DynFuture>> = Mutex::new(Vec::new());
+
+enum JoinState<T> {
+    Unawaited,
+    Awaited(Waker),
+    Ready(T),
+    Done,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut guard = self.state.lock().unwrap();
+        // Use JoinState::Done as a placeholder, to take ownership of T.
+        match mem::replace(&mut *guard, JoinState::Done) {
+            JoinState::Ready(value) => Poll::Ready(value),
+            JoinState::Unawaited | JoinState::Awaited(_) => {
+                // Replace the previous Waker, if any. We only need the most recent one.
+                *guard = JoinState::Awaited(context.waker().clone());
+                Poll::Pending
+            }
+            JoinState::Done => unreachable!("polled again after Ready"),
+        }
+    }
+}
+
+async fn wrap_with_join_state<F: Future>(future: F, join_state: Arc<Mutex<JoinState<F::Output>>>) {
+    let value = future.await;
+    let mut guard = join_state.lock().unwrap();
+    if let JoinState::Awaited(waker) = &*guard {
+        waker.wake_by_ref();
+    }
+    *guard = JoinState::Ready(value)
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&join_state),
+    };
+    let task = Box::pin(wrap_with_join_state(future, join_state));
+    NEW_TASKS.lock().unwrap().push(task);
+    join_handle
+}
+
+// In production we'd use AtomicBool instead of Mutex<bool>.
+struct AwakeFlag(Mutex<bool>);
+
+impl AwakeFlag {
+    fn check_and_clear(&self) -> bool {
+        let mut guard = self.0.lock().unwrap();
+        let check = *guard;
+        *guard = false;
+        check
+    }
+}
+
+impl Wake for AwakeFlag {
+    fn wake(self: Arc<Self>) {
+        *self.0.lock().unwrap() = true;
+    }
+}
+
+static POLL_FDS: Mutex<Vec<(RawFd, Waker)>> = Mutex::new(Vec::new());
+
+// A single value handed from one task to another, the same "Unawaited/Awaited/Ready" shape as
+// JoinState above, plus a Closed state for when the Sender is dropped without sending.
+mod oneshot {
+    use super::*;
+
+    enum State<T> {
+        Unawaited,
+        Awaited(Waker),
+        Ready(T),
+        Closed,
+        Done,
+    }
+
+    struct Shared<T> {
+        state: Mutex<State<T>>,
+    }
+
+    pub struct Sender<T> {
+        shared: Arc<Shared<T>>,
+    }
+
+    pub struct Receiver<T> {
+        shared: Arc<Shared<T>>,
+    }
+
+    pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::Unawaited),
+        });
+        (
+            Sender {
+                shared: Arc::clone(&shared),
+            },
+            Receiver { shared },
+        )
+    }
+
+    impl<T> Sender<T> {
+        pub fn send(self, value: T) {
+            let mut guard = self.shared.state.lock().unwrap();
+            if let State::Awaited(waker) = &*guard {
+                waker.wake_by_ref();
+            }
+            *guard = State::Ready(value);
+        }
+    }
+
+    impl<T> Drop for Sender<T> {
+        fn drop(&mut self) {
+            let mut guard = self.shared.state.lock().unwrap();
+            // If `send` already ran, the state is Ready (or Done), and this is a no-op.
+            match mem::replace(&mut *guard, State::Closed) {
+                State::Awaited(waker) => waker.wake(),
+                State::Unawaited => {}
+                other => *guard = other,
+            }
+        }
+    }
+
+    impl<T> Future for Receiver<T> {
+        type Output = Option<T>;
+
+        fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<T>> {
+            let mut guard = self.shared.state.lock().unwrap();
+            match mem::replace(&mut *guard, State::Done) {
+                State::Ready(value) => Poll::Ready(Some(value)),
+                State::Closed => Poll::Ready(None),
+                State::Unawaited | State::Awaited(_) => {
+                    *guard = State::Awaited(context.waker().clone());
+                    Poll::Pending
+                }
+                State::Done => unreachable!("polled again after Ready or Closed"),
+            }
+        }
+    }
+}
+
+// A bounded multi-producer single-consumer queue. Unlike oneshot::Shared above, there's no
+// Done/placeholder dance: the Receiver implements Future but behaves like a Stream rather than a
+// one-shot Future, so it can be polled (and Ready'd) over and over.
+mod mpsc {
+    use super::*;
+
+    struct Shared<T> {
+        queue: Mutex<VecDeque<T>>,
+        capacity: usize,
+        // Woken when the queue has room, because a `recv` just pulled an item off the front.
+        // Sender is Clone (multi-producer), so more than one Send can be blocked on a full queue
+        // at once; a single Option<Waker> slot would let a later sender's registration clobber an
+        // earlier one's, starving it forever even after capacity frees up.
+        send_wakers: Mutex<Vec<Waker>>,
+        // Woken when the queue has an item, because a `send` just pushed one onto the back, or
+        // because the last Sender was dropped and the Receiver needs to observe the closure.
+        recv_waker: Mutex<Option<Waker>>,
+        senders: Mutex<usize>,
+    }
+
+    pub struct Sender<T> {
+        shared: Arc<Shared<T>>,
+    }
+
+    pub struct Receiver<T> {
+        shared: Arc<Shared<T>>,
+    }
+
+    pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(VecDeque::new()),
+            capacity,
+            send_wakers: Mutex::new(Vec::new()),
+            recv_waker: Mutex::new(None),
+            senders: Mutex::new(1),
+        });
+        (
+            Sender {
+                shared: Arc::clone(&shared),
+            },
+            Receiver { shared },
+        )
+    }
+
+    impl<T> Clone for Sender<T> {
+        fn clone(&self) -> Self {
+            *self.shared.senders.lock().unwrap() += 1;
+            Sender {
+                shared: Arc::clone(&self.shared),
+            }
+        }
+    }
+
+    impl<T> Sender<T> {
+        pub fn send(&self, value: T) -> Send<'_, T> {
+            Send {
+                shared: &self.shared,
+                value: Some(value),
+            }
+        }
+    }
+
+    impl<T> Drop for Sender<T> {
+        fn drop(&mut self) {
+            let mut senders = self.shared.senders.lock().unwrap();
+            *senders -= 1;
+            if *senders == 0 {
+                if let Some(waker) = self.shared.recv_waker.lock().unwrap().take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+
+    pub struct Send<'a, T> {
+        shared: &'a Shared<T>,
+        value: Option<T>,
+    }
+
+    impl<'a, T> Future for Send<'a, T> {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+            let this = self.get_mut();
+            let mut queue = this.shared.queue.lock().unwrap();
+            if queue.len() < this.shared.capacity {
+                queue.push_back(this.value.take().expect("polled again after Ready"));
+                if let Some(waker) = this.shared.recv_waker.lock().unwrap().take() {
+                    waker.wake();
+                }
+                Poll::Ready(())
+            } else {
+                // Register this poll's waker unless we're already registered (e.g. this same Send
+                // was polled again before the queue had room); will_wake lets that check hold even
+                // though the waker handed to us may be a fresh clone each time.
+                let mut send_wakers = this.shared.send_wakers.lock().unwrap();
+                let waker = context.waker();
+                if !send_wakers.iter().any(|w| w.will_wake(waker)) {
+                    send_wakers.push(waker.clone());
+                }
+                Poll::Pending
+            }
+        }
+    }
+
+    impl<T> Future for Receiver<T> {
+        // None means every Sender has been dropped and the queue is empty: the stream is closed.
+        type Output = Option<T>;
+
+        fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<T>> {
+            let mut queue = self.shared.queue.lock().unwrap();
+            if let Some(value) = queue.pop_front() {
+                // Only one slot just freed up, but any number of Sends could be waiting on it;
+                // wake all of them and let them race to claim it, same as a condvar broadcast.
+                self.shared.send_wakers.lock().unwrap().drain(..).for_each(Waker::wake);
+                Poll::Ready(Some(value))
+            } else if *self.shared.senders.lock().unwrap() == 0 {
+                Poll::Ready(None)
+            } else {
+                *self.shared.recv_waker.lock().unwrap() = Some(context.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+async fn tcp_bind(address: &str) -> io::Result<TcpListener> {
+    // XXX: This is technically blocking. Assume it returns quickly.
+    let listener = TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+struct TcpAccept<'a> {
+    listener: &'a TcpListener,
+}
+
+impl<'a> Future for TcpAccept<'a> {
+    type Output = io::Result<TcpStream>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<TcpStream>> {
+        match self.listener.accept() {
+            Ok((stream, _)) => {
+                let result = stream.set_nonblocking(true);
+                Poll::Ready(result.and(Ok(stream)))
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                let raw_fd = self.listener.as_raw_fd();
+                let waker = context.waker().clone();
+                POLL_FDS.lock().unwrap().push((raw_fd, waker));
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+fn tcp_accept(listener: &TcpListener) -> TcpAccept {
+    TcpAccept { listener }
+}
+
+async fn foo_response(n: u64, mut socket: TcpStream) -> io::Result<()> {
+    // XXX: Assume the write buffer is large enough that we don't need to handle WouldBlock.
+    let start_msg = format!("start {n}\n");
+    socket.write_all(start_msg.as_bytes())?;
+    sleep(Duration::from_secs(1)).await;
+    let end_msg = format!("end {n}\n");
+    socket.write_all(end_msg.as_bytes())?;
+    Ok(())
+}
+
+// Instead of spawning one task per connection, a fixed pool of worker tasks pulls connections off
+// an mpsc::Receiver. `server_main` is the only Sender, so the workers see the channel close (and
+// exit) once it finishes accepting.
+async fn recv(receiver: &Mutex<mpsc::Receiver<(u64, TcpStream)>>) -> Option<(u64, TcpStream)> {
+    // Lock only long enough to poll once; holding the lock across .await would block every other
+    // worker trying to poll the same Receiver in the meantime.
+    std::future::poll_fn(|context| Pin::new(&mut *receiver.lock().unwrap()).poll(context)).await
+}
+
+async fn worker_main(receiver: Arc<Mutex<mpsc::Receiver<(u64, TcpStream)>>>) {
+    while let Some((n, socket)) = recv(&receiver).await {
+        foo_response(n, socket).await.unwrap();
+    }
+}
+
+async fn server_main(listener: TcpListener, sender: mpsc::Sender<(u64, TcpStream)>) -> io::Result<()> {
+    let mut n = 1;
+    loop {
+        let socket = tcp_accept(&listener).await?;
+        sender.send((n, socket)).await;
+        n += 1;
+    }
+}
+
+async fn async_main() -> io::Result<()> {
+    // Open the listener here, to avoid racing against the server thread.
+    let listener = tcp_bind("0.0.0.0:8000").await?;
+    let (sender, receiver) = mpsc::channel(16);
+    let receiver = Arc::new(Mutex::new(receiver));
+    let mut worker_handles = Vec::new();
+    for _ in 0..4 {
+        worker_handles.push(spawn(worker_main(Arc::clone(&receiver))));
+    }
+    let (done_sender, done_receiver) = oneshot::channel();
+    spawn(async move {
+        done_sender.send(server_main(listener, sender).await);
+    });
+    let result = done_receiver.await.expect("server_main task was cancelled");
+    for handle in worker_handles {
+        handle.await;
+    }
+    result
+}
+
+fn main() -> io::Result<()> {
+    let awake_flag = Arc::new(AwakeFlag(Mutex::new(false)));
+    let waker = Waker::from(Arc::clone(&awake_flag));
+    let mut context = Context::from_waker(&waker);
+    let mut main_task = Box::pin(async_main());
+    let mut other_tasks: Vec<DynFuture> = Vec::new();
+    loop {
+        // Poll the main task and exit immediately if it's done.
+        if let Poll::Ready(result) = main_task.as_mut().poll(&mut context) {
+            return result;
+        }
+        // Poll other tasks and remove any that are Ready.
+        let is_pending = |task: &mut DynFuture| task.as_mut().poll(&mut context).is_pending();
+        other_tasks.retain_mut(is_pending);
+        // Some tasks might have spawned new tasks. Pop from NEW_TASKS until it's empty. Note that
+        // we can't use while-let here, because that would keep NEW_TASKS locked in the loop body.
+        // See https://fasterthanli.me/articles/a-rust-match-made-in-hell.
+        loop {
+            let Some(mut task) = NEW_TASKS.lock().unwrap().pop() else {
+                break;
+            };
+            // Poll each new task now, instead of waiting for the next iteration of the main loop,
+            // to let them register wakeups. Drop the ones that return Ready. This poll can also
+            // spawn more tasks, so it's important that NEW_TASKS isn't locked here.
+            if task.as_mut().poll(&mut context).is_pending() {
+                other_tasks.push(task);
+            }
+        }
+        // Some tasks might wake other tasks. Re-poll if the AwakeFlag has been set. Polling
+        // futures that aren't ready yet is inefficient but allowed.
+        if awake_flag.check_and_clear() {
+            continue;
+        }
+        // All tasks are either sleeping or blocked on IO. Use libc::poll to wait for IO on any of
+        // the POLL_FDS. If there are any WAKE_TIMES, use the earliest as a timeout.
+        let mut poll_fds = POLL_FDS.lock().unwrap();
+        let mut poll_structs = Vec::new();
+        for &(raw_fd, _) in poll_fds.iter() {
+            poll_structs.push(libc::pollfd {
+                fd: raw_fd,
+                events: libc::POLLIN, // "poll input": wake when readable
+                revents: 0,           // return field, unused
+            });
+        }
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        let timeout_ms = if let Some(time) = wake_times.keys().next() {
+            let duration = time.saturating_duration_since(Instant::now());
+            duration.as_millis() as libc::c_int
+        } else {
+            -1 // infinite timeout
+        };
+        let poll_error_code = unsafe {
+            libc::poll(
+                poll_structs.as_mut_ptr(),
+                poll_structs.len() as libc::nfds_t,
+                timeout_ms,
+            )
+        };
+        if poll_error_code == -1 {
+            panic!("libc::poll failed: {}", io::Error::last_os_error());
+        }
+        // Invoke Wakers from WAKE_TIMES if their time has come.
+        while let Some(entry) = wake_times.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+        // Invoke all Wakers from POLL_FDS. This might wake futures that aren't ready yet, but if
+        // so they'll register another wakeup. It's inefficient but allowed.
+        poll_fds.drain(..).map(|pair| pair.1).for_each(Waker::wake);
+    }
+}
@@ -0,0 +1,429 @@
+use crossbeam_channel::{bounded, unbounded, Sender};
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::net::{TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static WAKE_TIMES: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            let wakers_vec = wake_times.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+// The global injector is where `spawn` and `Waker::wake` both put tasks. Worker threads drain
+// their own local queue first and only reach for the injector (and each other, via Stealer) when
+// they run out of local work. This is the same three-tier design as Tokio's and async-std's
+// multi-threaded schedulers.
+static INJECTOR: OnceLock<Injector<Arc<Task>>> = OnceLock::new();
+static STEALERS: OnceLock<Vec<Stealer<Arc<Task>>>> = OnceLock::new();
+
+fn injector() -> &'static Injector<Arc<Task>> {
+    INJECTOR.get_or_init(Injector::new)
+}
+
+struct Task {
+    future: Mutex<Option<DynFuture>>,
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        injector().push(Arc::clone(self));
+    }
+}
+
+fn poll_task(task: &Arc<Task>, context: &mut Context) {
+    let mut future_slot = task.future.lock().unwrap();
+    // The future might already be gone if the task was woken twice before being polled once.
+    let Some(future) = future_slot.as_mut() else {
+        return;
+    };
+    if future.as_mut().poll(context).is_ready() {
+        *future_slot = None;
+    }
+}
+
+enum JoinState<T> {
+    Unawaited,
+    Awaited(Waker),
+    Ready(T),
+    Done,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut guard = self.state.lock().unwrap();
+        // Use JoinState::Done as a placeholder, to take ownership of T.
+        match mem::replace(&mut *guard, JoinState::Done) {
+            JoinState::Ready(value) => Poll::Ready(value),
+            JoinState::Unawaited | JoinState::Awaited(_) => {
+                // Replace the previous Waker, if any. We only need the most recent one.
+                *guard = JoinState::Awaited(context.waker().clone());
+                Poll::Pending
+            }
+            JoinState::Done => unreachable!("polled again after Ready"),
+        }
+    }
+}
+
+async fn wrap_with_join_state<F: Future>(future: F, join_state: Arc<Mutex<JoinState<F::Output>>>) {
+    let value = future.await;
+    let mut guard = join_state.lock().unwrap();
+    if let JoinState::Awaited(waker) = &*guard {
+        waker.wake_by_ref();
+    }
+    *guard = JoinState::Ready(value)
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&join_state),
+    };
+    let wrapped = Box::pin(wrap_with_join_state(future, join_state));
+    injector().push(Arc::new(Task {
+        future: Mutex::new(Some(wrapped)),
+    }));
+    join_handle
+}
+
+// A fixed pool of plain OS threads for actually-blocking work (DNS resolution, file IO, CPU-bound
+// hashing, etc.) that would otherwise stall one of our worker threads and everything queued
+// behind it. Unlike `spawn`, jobs here are plain closures, not futures: there's nothing to poll,
+// they just run to completion on whichever blocking thread picks them up.
+static BLOCKING_QUEUE: OnceLock<Sender<Box<dyn FnOnce() + Send>>> = OnceLock::new();
+
+fn start_blocking_pool(num_threads: usize) -> Sender<Box<dyn FnOnce() + Send>> {
+    let (sender, receiver) = unbounded::<Box<dyn FnOnce() + Send>>();
+    for _ in 0..num_threads {
+        let receiver = receiver.clone();
+        thread::spawn(move || {
+            for job in receiver {
+                job();
+            }
+        });
+    }
+    sender
+}
+
+fn spawn_blocking<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&join_state),
+    };
+    let job = move || {
+        let value = f();
+        let mut guard = join_state.lock().unwrap();
+        if let JoinState::Awaited(waker) = mem::replace(&mut *guard, JoinState::Ready(value)) {
+            waker.wake();
+        }
+    };
+    BLOCKING_QUEUE
+        .get()
+        .expect("blocking pool not started")
+        .send(Box::new(job))
+        .expect("blocking pool is gone");
+    join_handle
+}
+
+// Readiness fds registered with epoll, each mapped to the Waker that's waiting on it. Unlike the
+// libc::poll version, this map isn't rebuilt into a fresh array on every wait: epoll_wait only
+// costs time proportional to the number of *ready* fds, not the number of registered ones.
+static WAKERS_BY_FD: Mutex<BTreeMap<RawFd, Waker>> = Mutex::new(BTreeMap::new());
+
+// Fds we've ever EPOLL_CTL_ADD'd. EPOLLONESHOT disarms a fd once its waker fires, and the main
+// loop removes it from WAKERS_BY_FD at that point, but neither of those un-registers the fd from
+// epoll itself. So this has to be tracked separately, or the next registration of the same fd
+// would wrongly retry EPOLL_CTL_ADD on an fd the kernel already knows about and get EEXIST.
+static REGISTERED_FDS: Mutex<BTreeSet<RawFd>> = Mutex::new(BTreeSet::new());
+
+fn epoll_fd() -> RawFd {
+    static EPOLL_FD: OnceLock<RawFd> = OnceLock::new();
+    *EPOLL_FD.get_or_init(|| {
+        let fd = unsafe { libc::epoll_create1(0) };
+        if fd == -1 {
+            panic!("epoll_create1 failed: {}", io::Error::last_os_error());
+        }
+        fd
+    })
+}
+
+// Register (or re-arm) interest in reading `raw_fd`, and remember the Waker to invoke once
+// epoll_wait reports it's readable.
+fn register_readable(raw_fd: RawFd, waker: Waker) {
+    let mut registered_fds = REGISTERED_FDS.lock().unwrap();
+    // EPOLLONESHOT means we have to re-arm with EPOLL_CTL_MOD (not _ADD) after the first wait.
+    // Whether this is the first registration is tracked by REGISTERED_FDS rather than
+    // WAKERS_BY_FD, because the latter gets its entry removed as soon as the waker fires, well
+    // before the fd is un-registered from epoll (which never happens here).
+    let op = if registered_fds.contains(&raw_fd) {
+        libc::EPOLL_CTL_MOD
+    } else {
+        libc::EPOLL_CTL_ADD
+    };
+    let mut event = libc::epoll_event {
+        events: (libc::EPOLLIN | libc::EPOLLONESHOT) as u32,
+        u64: raw_fd as u64,
+    };
+    let result = unsafe { libc::epoll_ctl(epoll_fd(), op, raw_fd, &mut event) };
+    if result == -1 {
+        panic!("epoll_ctl failed: {}", io::Error::last_os_error());
+    }
+    registered_fds.insert(raw_fd);
+    WAKERS_BY_FD.lock().unwrap().insert(raw_fd, waker);
+}
+
+async fn tcp_bind(address: &str) -> io::Result<TcpListener> {
+    // XXX: This is technically blocking. Assume it returns quickly.
+    let listener = TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+async fn tcp_connect(address: String) -> io::Result<TcpStream> {
+    // TcpStream::connect can genuinely block on DNS resolution, unlike TcpListener::bind above.
+    // Run it on the blocking pool instead of assuming it returns quickly.
+    let socket = spawn_blocking(move || TcpStream::connect(&address)).await?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+struct TcpAccept<'a> {
+    listener: &'a TcpListener,
+}
+
+impl<'a> Future for TcpAccept<'a> {
+    type Output = io::Result<TcpStream>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<TcpStream>> {
+        match self.listener.accept() {
+            Ok((stream, _)) => {
+                let result = stream.set_nonblocking(true);
+                Poll::Ready(result.and(Ok(stream)))
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                register_readable(self.listener.as_raw_fd(), context.waker().clone());
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+fn tcp_accept(listener: &TcpListener) -> TcpAccept {
+    TcpAccept { listener }
+}
+
+struct Copy<'a, R, W> {
+    reader: &'a mut R,
+    writer: &'a mut W,
+}
+
+impl<'a, R: Read + AsRawFd, W: Write> Future for Copy<'a, R, W> {
+    type Output = io::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        let Copy { reader, writer } = &mut *self.as_mut();
+        match io::copy(reader, writer) {
+            Ok(_) => Poll::Ready(Ok(())),
+            // XXX: Assume that the writer will never block.
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                register_readable(self.reader.as_raw_fd(), context.waker().clone());
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+fn copy<'a, R, W>(reader: &'a mut R, writer: &'a mut W) -> Copy<'a, R, W> {
+    Copy { reader, writer }
+}
+
+async fn foo_response(n: u64, mut socket: TcpStream) -> io::Result<()> {
+    // XXX: Assume the write buffer is large enough that we don't need to handle WouldBlock.
+    // Using format! instead of write! avoids breaking up lines across multiple writes. This is
+    // easier than doing line buffering on the client side.
+    let start_msg = format!("start {n}\n");
+    socket.write_all(start_msg.as_bytes())?;
+    sleep(Duration::from_secs(1)).await;
+    let end_msg = format!("end {n}\n");
+    socket.write_all(end_msg.as_bytes())?;
+    Ok(())
+}
+
+async fn server_main(listener: TcpListener) -> io::Result<()> {
+    let mut n = 1;
+    loop {
+        let socket = tcp_accept(&listener).await?;
+        spawn(async move { foo_response(n, socket).await.unwrap() });
+        n += 1;
+    }
+}
+
+async fn foo_request() -> io::Result<()> {
+    let mut socket = tcp_connect("localhost:8000".to_string()).await?;
+    copy(&mut socket, &mut io::stdout()).await?;
+    Ok(())
+}
+
+async fn async_main() -> io::Result<()> {
+    // Open the listener here, to avoid racing against the server thread.
+    let listener = tcp_bind("0.0.0.0:8000").await?;
+    spawn(async { server_main(listener).await.unwrap() });
+    let mut task_handles = Vec::new();
+    for _ in 1..=10 {
+        task_handles.push(spawn(foo_request()));
+    }
+    for handle in task_handles {
+        handle.await?;
+    }
+    Ok(())
+}
+
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+// Look for work in our own queue first, then in the injector (taking a whole batch at once to
+// amortize the contention), and finally by stealing from another worker. This three-tier lookup
+// is the standard crossbeam-deque pattern; see its docs for the canonical version.
+fn find_task(local: &Worker<Arc<Task>>, injector: &Injector<Arc<Task>>, stealers: &[Stealer<Arc<Task>>]) -> Option<Arc<Task>> {
+    local.pop().or_else(|| {
+        std::iter::repeat_with(|| {
+            injector
+                .steal_batch_and_pop(local)
+                .or_else(|| stealers.iter().map(|s| s.steal()).collect())
+        })
+        .find(|s| !s.is_retry())
+        .and_then(|s| s.success())
+    })
+}
+
+fn worker_loop(local: Worker<Arc<Task>>) {
+    let stealers = STEALERS.get().unwrap();
+    while !SHUTDOWN.load(Ordering::Relaxed) {
+        match find_task(&local, injector(), stealers) {
+            Some(task) => {
+                let waker = Waker::from(Arc::clone(&task));
+                let mut context = Context::from_waker(&waker);
+                poll_task(&task, &mut context);
+            }
+            // No work anywhere right now; back off briefly rather than spinning. The reactor
+            // thread will push more work onto the injector as timers and I/O become ready.
+            None => thread::sleep(Duration::from_millis(1)),
+        }
+    }
+}
+
+// The reactor owns WAKE_TIMES and the epoll instance, and runs on its own thread so that worker
+// threads never have to take turns blocking in epoll_wait.
+fn reactor_loop() {
+    while !SHUTDOWN.load(Ordering::Relaxed) {
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        let timeout_ms = if let Some(time) = wake_times.keys().next() {
+            let duration = time.saturating_duration_since(Instant::now());
+            duration.as_millis().min(100) as libc::c_int
+        } else {
+            100 // Wake up periodically to notice SHUTDOWN even with nothing scheduled.
+        };
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; 1024];
+        let num_events = unsafe {
+            libc::epoll_wait(epoll_fd(), events.as_mut_ptr(), events.len() as libc::c_int, timeout_ms)
+        };
+        if num_events == -1 {
+            panic!("epoll_wait failed: {}", io::Error::last_os_error());
+        }
+        while let Some(entry) = wake_times.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+        let mut wakers_by_fd = WAKERS_BY_FD.lock().unwrap();
+        for event in &events[..num_events as usize] {
+            if let Some(waker) = wakers_by_fd.remove(&(event.u64 as RawFd)) {
+                waker.wake();
+            }
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    BLOCKING_QUEUE
+        .set(start_blocking_pool(4))
+        .unwrap_or_else(|_| panic!("set called twice"));
+    let num_workers = thread::available_parallelism().map_or(4, |n| n.get());
+    let workers: Vec<Worker<Arc<Task>>> = (0..num_workers).map(|_| Worker::new_fifo()).collect();
+    STEALERS
+        .set(workers.iter().map(Worker::stealer).collect())
+        .unwrap_or_else(|_| panic!("set called twice"));
+    let worker_threads: Vec<_> = workers
+        .into_iter()
+        .map(|local| thread::spawn(move || worker_loop(local)))
+        .collect();
+    let reactor_thread = thread::spawn(reactor_loop);
+
+    // A one-slot channel just to get the final io::Result back out to the main thread.
+    let (result_sender, result_receiver) = bounded(1);
+    spawn(async move {
+        let result = async_main().await;
+        result_sender.send(result).expect("main thread is waiting");
+    });
+    let result = result_receiver.recv().expect("async_main task panicked");
+
+    SHUTDOWN.store(true, Ordering::Relaxed);
+    for handle in worker_threads {
+        handle.join().expect("worker thread panicked");
+    }
+    reactor_thread.join().expect("reactor thread panicked");
+    result
+}
@@ -0,0 +1,396 @@
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static WAKE_TIMES: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            let wakers_vec = wake_times.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+static NEW_TASKS: Mutex<Vec<(DynFuture, Arc<AtomicBool>)>> = Mutex::new(Vec::new());
+
+#[derive(Debug)]
+struct Aborted;
+
+impl fmt::Display for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "task was aborted")
+    }
+}
+
+impl std::error::Error for Aborted {}
+
+enum JoinState<T> {
+    Unawaited,
+    Awaited(Waker),
+    Ready(T),
+    Aborted,
+    Done,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<T> JoinHandle<T> {
+    fn abort(&self) {
+        self.cancelled.store(true, Ordering::Release);
+        let mut guard = self.state.lock().unwrap();
+        if let JoinState::Awaited(waker) = mem::replace(&mut *guard, JoinState::Aborted) {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, Aborted>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Result<T, Aborted>> {
+        let mut guard = self.state.lock().unwrap();
+        // Use JoinState::Done as a placeholder, to take ownership of T.
+        match mem::replace(&mut *guard, JoinState::Done) {
+            JoinState::Ready(value) => Poll::Ready(Ok(value)),
+            JoinState::Aborted => Poll::Ready(Err(Aborted)),
+            JoinState::Unawaited | JoinState::Awaited(_) => {
+                // Replace the previous Waker, if any. We only need the most recent one.
+                *guard = JoinState::Awaited(context.waker().clone());
+                Poll::Pending
+            }
+            JoinState::Done => unreachable!("polled again after Ready or Aborted"),
+        }
+    }
+}
+
+async fn wrap_with_join_state<F: Future>(
+    future: F,
+    join_state: Arc<Mutex<JoinState<F::Output>>>,
+    cancelled: Arc<AtomicBool>,
+) {
+    let value = future.await;
+    let mut guard = join_state.lock().unwrap();
+    // Don't clobber an abort() that raced with this future's own completion.
+    if cancelled.load(Ordering::Acquire) {
+        return;
+    }
+    if let JoinState::Awaited(waker) = &*guard {
+        waker.wake_by_ref();
+    }
+    *guard = JoinState::Ready(value)
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&join_state),
+        cancelled: Arc::clone(&cancelled),
+    };
+    let task = Box::pin(wrap_with_join_state(future, join_state, Arc::clone(&cancelled)));
+    NEW_TASKS.lock().unwrap().push((task, cancelled));
+    join_handle
+}
+
+// In production we'd use AtomicBool instead of Mutex<bool>.
+struct AwakeFlag(Mutex<bool>);
+
+impl AwakeFlag {
+    fn check_and_clear(&self) -> bool {
+        let mut guard = self.0.lock().unwrap();
+        let check = *guard;
+        *guard = false;
+        check
+    }
+}
+
+impl Wake for AwakeFlag {
+    fn wake(self: Arc<Self>) {
+        *self.0.lock().unwrap() = true;
+    }
+}
+
+const MAX_BLOCKING_THREADS: usize = 512;
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// A small growable pool of plain OS threads for blocking/CPU-bound work, the same fix tokio's own
+// blocking pool provides: a thread that calls thread::sleep (or does a blocking syscall, or hashes
+// a big buffer) stalls everything queued behind it if it runs on an async worker thread. Threads
+// here are spun up on demand, up to MAX_BLOCKING_THREADS, and exit once IDLE_TIMEOUT passes
+// without a new job, so the demo doesn't leave a pile of idle threads running.
+struct BlockingPool {
+    sender: Sender<Box<dyn FnOnce() + Send>>,
+    receiver: Receiver<Box<dyn FnOnce() + Send>>,
+    idle_threads: AtomicUsize,
+    total_threads: Mutex<usize>,
+}
+
+fn blocking_pool() -> &'static BlockingPool {
+    static POOL: OnceLock<BlockingPool> = OnceLock::new();
+    POOL.get_or_init(|| {
+        let (sender, receiver) = unbounded();
+        BlockingPool {
+            sender,
+            receiver,
+            idle_threads: AtomicUsize::new(0),
+            total_threads: Mutex::new(0),
+        }
+    })
+}
+
+impl BlockingPool {
+    fn spawn_job(&'static self, job: Box<dyn FnOnce() + Send>) {
+        self.sender.send(job).expect("pool receiver never dropped");
+        // If no thread is currently idle, grow the pool (up to the cap) so this job, and whatever
+        // else piles up behind it, doesn't wait on a thread that might be busy for a while.
+        if self.idle_threads.load(Ordering::Acquire) == 0 {
+            let mut total = self.total_threads.lock().unwrap();
+            if *total < MAX_BLOCKING_THREADS {
+                *total += 1;
+                self.spawn_thread();
+            }
+        }
+    }
+
+    fn spawn_thread(&'static self) {
+        thread::spawn(move || {
+            loop {
+                self.idle_threads.fetch_add(1, Ordering::AcqRel);
+                let job = self.receiver.recv_timeout(IDLE_TIMEOUT);
+                self.idle_threads.fetch_sub(1, Ordering::AcqRel);
+                match job {
+                    Ok(job) => job(),
+                    Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            *self.total_threads.lock().unwrap() -= 1;
+        });
+        // The thread we just spun up decrements this itself right before it exits.
+        // (total_threads is only ever incremented by the caller of spawn_thread, under the lock.)
+    }
+}
+
+fn spawn_blocking<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    // XXX: Once `f` has been handed to a pool thread, there's no way to actually interrupt it
+    // short of killing the thread. `cancelled` isn't even consulted here; calling `.abort()` on
+    // this handle just stops us from caring about the result.
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&join_state),
+        cancelled: Arc::clone(&cancelled),
+    };
+    let job = move || {
+        let value = f();
+        let mut guard = join_state.lock().unwrap();
+        if let JoinState::Awaited(waker) = mem::replace(&mut *guard, JoinState::Ready(value)) {
+            waker.wake();
+        }
+    };
+    blocking_pool().spawn_job(Box::new(job));
+    join_handle
+}
+
+async fn accept(listener: &mut TcpListener) -> io::Result<(TcpStream, SocketAddr)> {
+    std::future::poll_fn(|context| match listener.accept() {
+        Ok((stream, addr)) => {
+            stream.set_nonblocking(true)?;
+            Poll::Ready(Ok((stream, addr)))
+        }
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+            // TODO: This is a busy loop.
+            context.waker().wake_by_ref();
+            Poll::Pending
+        }
+        Err(e) => Poll::Ready(Err(e)),
+    })
+    .await
+}
+
+async fn write_all(mut buf: &[u8], stream: &mut TcpStream) -> io::Result<()> {
+    std::future::poll_fn(|context| {
+        while !buf.is_empty() {
+            match stream.write(buf) {
+                Ok(n) if n == 0 => {
+                    let e = io::Error::from(io::ErrorKind::WriteZero);
+                    return Poll::Ready(Err(e));
+                }
+                Ok(n) => buf = &buf[n..],
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    // TODO: This is a busy loop.
+                    context.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+        Poll::Ready(Ok(()))
+    })
+    .await
+}
+
+// A stand-in for CPU-bound work (e.g. hashing a big request body) that would stall the event
+// loop if it ran directly inside an async fn, the same mistake foo_blocking.rs makes with a bare
+// thread::sleep in Foo::poll.
+async fn compute_busy_checksum(n: u64) -> u64 {
+    spawn_blocking(move || {
+        thread::sleep(Duration::from_millis(50));
+        n.wrapping_mul(2654435761)
+    })
+    .await
+    .expect("blocking task was aborted")
+}
+
+async fn one_response(mut socket: TcpStream, n: u64) -> io::Result<()> {
+    // Using format! instead of write! avoids breaking up lines across multiple writes. This is
+    // easier than doing line buffering on the client side.
+    let start_msg = format!("start {n}\n");
+    write_all(start_msg.as_bytes(), &mut socket).await?;
+    sleep(Duration::from_secs(1)).await;
+    let checksum = compute_busy_checksum(n).await;
+    let end_msg = format!("end {n} checksum {checksum}\n");
+    write_all(end_msg.as_bytes(), &mut socket).await?;
+    Ok(())
+}
+
+async fn server_main(mut listener: TcpListener) -> io::Result<()> {
+    let mut n = 1;
+    loop {
+        let (socket, _) = accept(&mut listener).await?;
+        spawn(async move { one_response(socket, n).await.unwrap() });
+        n += 1;
+    }
+}
+
+async fn client_main() -> io::Result<()> {
+    // XXX: Assume that connect() returns quickly.
+    let mut socket = TcpStream::connect("localhost:8000")?;
+    socket.set_nonblocking(true)?;
+    let mut buf = [0; 1024];
+    loop {
+        let n = std::future::poll_fn(|context| match socket.read(&mut buf) {
+            Ok(n) => Poll::Ready(Ok(n)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                context.waker().wake_by_ref();
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        })
+        .await?;
+        if n == 0 {
+            return Ok(());
+        }
+        io::stdout().write_all(&buf[..n])?;
+    }
+}
+
+async fn async_main() -> io::Result<()> {
+    // Avoid a race between bind and connect by binding first.
+    let listener = TcpListener::bind("0.0.0.0:8000")?;
+    listener.set_nonblocking(true)?;
+    let server_handle = spawn(async { server_main(listener).await.unwrap() });
+    // Run ten clients as ten different tasks.
+    let mut task_handles = Vec::new();
+    for _ in 1..=10 {
+        task_handles.push(spawn(client_main()));
+    }
+    for handle in task_handles {
+        handle.await.expect("client task was aborted")?;
+    }
+    // The server task would otherwise run forever; abort it now that every client is done.
+    server_handle.abort();
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    let awake_flag = Arc::new(AwakeFlag(Mutex::new(false)));
+    let waker = Waker::from(Arc::clone(&awake_flag));
+    let mut context = Context::from_waker(&waker);
+    let mut main_task = Box::pin(async_main());
+    let mut other_tasks: Vec<(DynFuture, Arc<AtomicBool>)> = Vec::new();
+    loop {
+        // Poll the main task and exit immediately if it's done.
+        if let Poll::Ready(result) = main_task.as_mut().poll(&mut context) {
+            return result;
+        }
+        // Poll other tasks and remove any that are Ready or have been aborted.
+        let is_pending = |(task, cancelled): &mut (DynFuture, Arc<AtomicBool>)| {
+            !cancelled.load(Ordering::Acquire) && task.as_mut().poll(&mut context).is_pending()
+        };
+        other_tasks.retain_mut(is_pending);
+        // Some tasks might have spawned new tasks. Pop from NEW_TASKS until it's empty. Note that
+        // we can't use while-let here, because that would keep NEW_TASKS locked in the loop body.
+        // See https://fasterthanli.me/articles/a-rust-match-made-in-hell.
+        loop {
+            let Some((mut task, cancelled)) = NEW_TASKS.lock().unwrap().pop() else {
+                break;
+            };
+            if cancelled.load(Ordering::Acquire) {
+                continue; // Aborted before it was ever polled once.
+            }
+            // Poll each new task now, instead of waiting for the next iteration of the main loop,
+            // to let them register wakeups. Drop the ones that return Ready. This poll can also
+            // spawn more tasks, so it's important that NEW_TASKS isn't locked here.
+            if task.as_mut().poll(&mut context).is_pending() {
+                other_tasks.push((task, cancelled));
+            }
+        }
+        // Some tasks might wake other tasks. Re-poll if the AwakeFlag has been set. Polling
+        // futures that aren't ready yet is inefficient but allowed.
+        if awake_flag.check_and_clear() {
+            continue;
+        }
+        // Sleep until the next Waker is scheduled and then invoke Wakers that are ready.
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        let next_wake = wake_times.keys().next().expect("sleep forever?");
+        thread::sleep(next_wake.saturating_duration_since(Instant::now()));
+        while let Some(entry) = wake_times.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
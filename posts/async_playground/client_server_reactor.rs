@@ -0,0 +1,373 @@
+use slab::Slab;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::{Duration, Instant};
+
+static WAKE_TIMES: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            let wakers_vec = wake_times.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+static NEW_TASKS: Mutex<Vec<DynFuture>> = Mutex::new(Vec::new());
+
+enum JoinState<T> {
+    Unawaited,
+    Awaited(Waker),
+    Ready(T),
+    Done,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut guard = self.state.lock().unwrap();
+        // Use JoinState::Done as a placeholder, to take ownership of T.
+        match mem::replace(&mut *guard, JoinState::Done) {
+            JoinState::Ready(value) => Poll::Ready(value),
+            JoinState::Unawaited | JoinState::Awaited(_) => {
+                // Replace the previous Waker, if any. We only need the most recent one.
+                *guard = JoinState::Awaited(context.waker().clone());
+                Poll::Pending
+            }
+            JoinState::Done => unreachable!("polled again after Ready"),
+        }
+    }
+}
+
+async fn wrap_with_join_state<F: Future>(future: F, join_state: Arc<Mutex<JoinState<F::Output>>>) {
+    let value = future.await;
+    let mut guard = join_state.lock().unwrap();
+    if let JoinState::Awaited(waker) = &*guard {
+        waker.wake_by_ref();
+    }
+    *guard = JoinState::Ready(value)
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&join_state),
+    };
+    let task = Box::pin(wrap_with_join_state(future, join_state));
+    NEW_TASKS.lock().unwrap().push(task);
+    join_handle
+}
+
+// In production we'd use AtomicBool instead of Mutex<bool>.
+struct AwakeFlag(Mutex<bool>);
+
+impl AwakeFlag {
+    fn check_and_clear(&self) -> bool {
+        let mut guard = self.0.lock().unwrap();
+        let check = *guard;
+        *guard = false;
+        check
+    }
+}
+
+impl Wake for AwakeFlag {
+    fn wake(self: Arc<Self>) {
+        *self.0.lock().unwrap() = true;
+    }
+}
+
+// One registration in the Reactor's slab: the raw fd it was registered with, plus one Waker slot
+// each for read- and write-readiness. A single edge-triggered epoll registration can wake both a
+// reader and a writer that are both waiting on the same fd.
+struct Entry {
+    fd: RawFd,
+    readable: Mutex<Option<Waker>>,
+    writable: Mutex<Option<Waker>>,
+}
+
+// Modeled on smol's reactor: one epoll instance, with each registered fd's bookkeeping kept in a
+// Slab so the slab key doubles as the epoll token (`u64` in `epoll_event`).
+struct Reactor {
+    epoll_fd: RawFd,
+    entries: Mutex<Slab<Arc<Entry>>>,
+}
+
+impl Reactor {
+    fn new() -> Reactor {
+        let epoll_fd = unsafe { libc::epoll_create1(0) };
+        if epoll_fd == -1 {
+            panic!("epoll_create1 failed: {}", io::Error::last_os_error());
+        }
+        Reactor {
+            epoll_fd,
+            entries: Mutex::new(Slab::new()),
+        }
+    }
+
+    // Register `fd` for both read- and write-readiness, edge-triggered. Returns the slab key,
+    // which callers hand back to `deregister` once they're done with the fd.
+    fn register(&self, fd: RawFd) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let key = entries.insert(Arc::new(Entry {
+            fd,
+            readable: Mutex::new(None),
+            writable: Mutex::new(None),
+        }));
+        let mut event = libc::epoll_event {
+            events: (libc::EPOLLIN | libc::EPOLLOUT | libc::EPOLLET) as u32,
+            u64: key as u64,
+        };
+        let result = unsafe { libc::epoll_ctl(self.epoll_fd, libc::EPOLL_CTL_ADD, fd, &mut event) };
+        if result == -1 {
+            panic!("epoll_ctl(ADD) failed: {}", io::Error::last_os_error());
+        }
+        key
+    }
+
+    fn deregister(&self, key: usize, fd: RawFd) {
+        let result = unsafe {
+            libc::epoll_ctl(self.epoll_fd, libc::EPOLL_CTL_DEL, fd, std::ptr::null_mut())
+        };
+        if result == -1 {
+            panic!("epoll_ctl(DEL) failed: {}", io::Error::last_os_error());
+        }
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    fn entry(&self, key: usize) -> Arc<Entry> {
+        Arc::clone(&self.entries.lock().unwrap()[key])
+    }
+
+    // Block on epoll_wait and wake whichever read/write wakers are ready. `timeout_ms` mirrors
+    // the libc::poll/epoll_wait convention: -1 means wait forever.
+    fn wait(&self, timeout_ms: libc::c_int) {
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; 1024];
+        let num_events = unsafe {
+            libc::epoll_wait(self.epoll_fd, events.as_mut_ptr(), events.len() as libc::c_int, timeout_ms)
+        };
+        if num_events == -1 {
+            panic!("epoll_wait failed: {}", io::Error::last_os_error());
+        }
+        let entries = self.entries.lock().unwrap();
+        for event in &events[..num_events as usize] {
+            let Some(entry) = entries.get(event.u64 as usize) else {
+                continue;
+            };
+            if event.events & (libc::EPOLLIN | libc::EPOLLHUP | libc::EPOLLERR) as u32 != 0 {
+                if let Some(waker) = entry.readable.lock().unwrap().take() {
+                    waker.wake();
+                }
+            }
+            if event.events & (libc::EPOLLOUT | libc::EPOLLHUP | libc::EPOLLERR) as u32 != 0 {
+                if let Some(waker) = entry.writable.lock().unwrap().take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+fn reactor() -> &'static Reactor {
+    static REACTOR: OnceLock<Reactor> = OnceLock::new();
+    REACTOR.get_or_init(Reactor::new)
+}
+
+async fn accept(listener: &mut TcpListener) -> io::Result<(TcpStream, SocketAddr)> {
+    let key = reactor().register(listener.as_raw_fd());
+    let result = std::future::poll_fn(|context| match listener.accept() {
+        Ok((stream, addr)) => {
+            let result = stream.set_nonblocking(true);
+            Poll::Ready(result.and(Ok((stream, addr))))
+        }
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+            *reactor().entry(key).readable.lock().unwrap() = Some(context.waker().clone());
+            Poll::Pending
+        }
+        Err(e) => Poll::Ready(Err(e)),
+    })
+    .await;
+    reactor().deregister(key, listener.as_raw_fd());
+    result
+}
+
+async fn write_all(mut buf: &[u8], stream: &mut TcpStream) -> io::Result<()> {
+    let key = reactor().register(stream.as_raw_fd());
+    let result = std::future::poll_fn(|context| {
+        while !buf.is_empty() {
+            match stream.write(buf) {
+                Ok(n) if n == 0 => {
+                    let e = io::Error::from(io::ErrorKind::WriteZero);
+                    return Poll::Ready(Err(e));
+                }
+                Ok(n) => buf = &buf[n..],
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    *reactor().entry(key).writable.lock().unwrap() = Some(context.waker().clone());
+                    return Poll::Pending;
+                }
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+        Poll::Ready(Ok(()))
+    })
+    .await;
+    reactor().deregister(key, stream.as_raw_fd());
+    result
+}
+
+async fn print_all(stream: &mut TcpStream) -> io::Result<()> {
+    let key = reactor().register(stream.as_raw_fd());
+    let mut buf = [0; 1024];
+    let result = std::future::poll_fn(|context| {
+        // Edge-triggered: we have to keep reading until WouldBlock, or we'll never see another
+        // readiness notification for bytes that arrived after the last one we drained.
+        loop {
+            match stream.read(&mut buf) {
+                Ok(n) if n == 0 => return Poll::Ready(Ok(())), // EOF
+                // Assume that printing doesn't block.
+                Ok(n) => io::stdout().write_all(&buf[..n])?,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    *reactor().entry(key).readable.lock().unwrap() = Some(context.waker().clone());
+                    return Poll::Pending;
+                }
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    })
+    .await;
+    reactor().deregister(key, stream.as_raw_fd());
+    result
+}
+
+async fn one_response(mut socket: TcpStream, n: u64) -> io::Result<()> {
+    // Using format! instead of write! avoids breaking up lines across multiple writes. This is
+    // easier than doing line buffering on the client side.
+    let start_msg = format!("start {n}\n");
+    write_all(start_msg.as_bytes(), &mut socket).await?;
+    sleep(Duration::from_secs(1)).await;
+    let end_msg = format!("end {n}\n");
+    write_all(end_msg.as_bytes(), &mut socket).await?;
+    Ok(())
+}
+
+async fn server_main(mut listener: TcpListener) -> io::Result<()> {
+    let mut n = 1;
+    loop {
+        let (socket, _) = accept(&mut listener).await?;
+        spawn(async move { one_response(socket, n).await.unwrap() });
+        n += 1;
+    }
+}
+
+async fn client_main() -> io::Result<()> {
+    // XXX: Assume that connect() returns quickly.
+    let mut socket = TcpStream::connect("localhost:8000")?;
+    socket.set_nonblocking(true)?;
+    print_all(&mut socket).await?;
+    Ok(())
+}
+
+async fn async_main() -> io::Result<()> {
+    // Avoid a race between bind and connect by binding first.
+    let listener = TcpListener::bind("0.0.0.0:8000")?;
+    listener.set_nonblocking(true)?;
+    // Start the server on a background task.
+    spawn(async { server_main(listener).await.unwrap() });
+    // Run ten clients as ten different tasks.
+    let mut task_handles = Vec::new();
+    for _ in 1..=10 {
+        task_handles.push(spawn(client_main()));
+    }
+    for handle in task_handles {
+        handle.await?;
+    }
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    let awake_flag = Arc::new(AwakeFlag(Mutex::new(false)));
+    let waker = Waker::from(Arc::clone(&awake_flag));
+    let mut context = Context::from_waker(&waker);
+    let mut main_task = Box::pin(async_main());
+    let mut other_tasks: Vec<DynFuture> = Vec::new();
+    loop {
+        // Poll the main task and exit immediately if it's done.
+        if let Poll::Ready(result) = main_task.as_mut().poll(&mut context) {
+            return result;
+        }
+        // Poll other tasks and remove any that are Ready.
+        let is_pending = |task: &mut DynFuture| task.as_mut().poll(&mut context).is_pending();
+        other_tasks.retain_mut(is_pending);
+        // Some tasks might have spawned new tasks. Pop from NEW_TASKS until it's empty. Note that
+        // we can't use while-let here, because that would keep NEW_TASKS locked in the loop body.
+        // See https://fasterthanli.me/articles/a-rust-match-made-in-hell.
+        loop {
+            let Some(mut task) = NEW_TASKS.lock().unwrap().pop() else {
+                break;
+            };
+            // Poll each new task now, instead of waiting for the next iteration of the main loop,
+            // to let them register wakeups. Drop the ones that return Ready. This poll can also
+            // spawn more tasks, so it's important that NEW_TASKS isn't locked here.
+            if task.as_mut().poll(&mut context).is_pending() {
+                other_tasks.push(task);
+            }
+        }
+        // Some tasks might wake other tasks. Re-poll if the AwakeFlag has been set. Polling
+        // futures that aren't ready yet is inefficient but allowed.
+        if awake_flag.check_and_clear() {
+            continue;
+        }
+        // All tasks are either sleeping or blocked on IO. Instead of the `thread::sleep` +
+        // self-waking busy loop this replaces, block in epoll_wait, with the earliest WAKE_TIMES
+        // entry (if any) as the timeout.
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        let timeout_ms = if let Some(time) = wake_times.keys().next() {
+            let duration = time.saturating_duration_since(Instant::now());
+            duration.as_millis() as libc::c_int
+        } else {
+            -1 // infinite timeout
+        };
+        reactor().wait(timeout_ms);
+        while let Some(entry) = wake_times.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
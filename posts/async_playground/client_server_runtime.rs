@@ -0,0 +1,690 @@
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+#[derive(Debug)]
+struct Aborted;
+
+impl fmt::Display for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "task was aborted")
+    }
+}
+
+impl std::error::Error for Aborted {}
+
+enum JoinState<T> {
+    Unawaited,
+    Awaited(Waker),
+    Ready(T),
+    Aborted,
+    Done,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<T> JoinHandle<T> {
+    fn abort(&self) {
+        self.cancelled.store(true, Ordering::Release);
+        let mut guard = self.state.lock().unwrap();
+        if let JoinState::Awaited(waker) = mem::replace(&mut *guard, JoinState::Aborted) {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, Aborted>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Result<T, Aborted>> {
+        let mut guard = self.state.lock().unwrap();
+        // Use JoinState::Done as a placeholder, to take ownership of T.
+        match mem::replace(&mut *guard, JoinState::Done) {
+            JoinState::Ready(value) => Poll::Ready(Ok(value)),
+            JoinState::Aborted => Poll::Ready(Err(Aborted)),
+            JoinState::Unawaited | JoinState::Awaited(_) => {
+                // Replace the previous Waker, if any. We only need the most recent one.
+                *guard = JoinState::Awaited(context.waker().clone());
+                Poll::Pending
+            }
+            JoinState::Done => unreachable!("polled again after Ready or Aborted"),
+        }
+    }
+}
+
+async fn wrap_with_join_state<F: Future>(
+    future: F,
+    join_state: Arc<Mutex<JoinState<F::Output>>>,
+    cancelled: Arc<AtomicBool>,
+) {
+    let value = future.await;
+    let mut guard = join_state.lock().unwrap();
+    // Don't clobber an abort() that raced with this future's own completion.
+    if cancelled.load(Ordering::Acquire) {
+        return;
+    }
+    if let JoinState::Awaited(waker) = &*guard {
+        waker.wake_by_ref();
+    }
+    *guard = JoinState::Ready(value)
+}
+
+// In production we'd use AtomicBool instead of Mutex<bool>.
+struct AwakeFlag(Mutex<bool>);
+
+impl AwakeFlag {
+    fn check_and_clear(&self) -> bool {
+        let mut guard = self.0.lock().unwrap();
+        let check = *guard;
+        *guard = false;
+        check
+    }
+}
+
+impl Wake for AwakeFlag {
+    fn wake(self: Arc<Self>) {
+        *self.0.lock().unwrap() = true;
+    }
+}
+
+// Everything that used to be a process-global static (NEW_TASKS, WAKE_TIMES) now lives here
+// instead, so that independent Runtimes (e.g. one per test) don't step on each other's state.
+// This is the piece that turns the teaching executor into something usable as a library rather
+// than a single hardcoded `fn main`.
+struct Runtime {
+    new_tasks: Mutex<Vec<(DynFuture, Arc<AtomicBool>)>>,
+    wake_times: Mutex<BTreeMap<Instant, Vec<Waker>>>,
+}
+
+impl Runtime {
+    fn new() -> Arc<Runtime> {
+        Arc::new(Runtime {
+            new_tasks: Mutex::new(Vec::new()),
+            wake_times: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    fn spawn<F, T>(&self, future: F) -> JoinHandle<T>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let join_handle = JoinHandle {
+            state: Arc::clone(&join_state),
+            cancelled: Arc::clone(&cancelled),
+        };
+        let task = Box::pin(wrap_with_join_state(future, join_state, Arc::clone(&cancelled)));
+        self.new_tasks.lock().unwrap().push((task, cancelled));
+        join_handle
+    }
+
+    fn register_wake_time(&self, wake_time: Instant, waker: Waker) {
+        let mut wake_times = self.wake_times.lock().unwrap();
+        wake_times.entry(wake_time).or_default().push(waker);
+    }
+
+    fn block_on<F: Future>(self: &Arc<Self>, future: F) -> F::Output {
+        CURRENT.with_borrow_mut(|current| {
+            assert!(current.is_none(), "block_on called re-entrantly");
+            *current = Some(Arc::clone(self));
+        });
+        let result = self.drive(future);
+        CURRENT.with_borrow_mut(|current| *current = None);
+        result
+    }
+
+    fn drive<F: Future>(&self, future: F) -> F::Output {
+        let awake_flag = Arc::new(AwakeFlag(Mutex::new(false)));
+        let waker = Waker::from(Arc::clone(&awake_flag));
+        let mut context = Context::from_waker(&waker);
+        let mut main_task = Box::pin(future);
+        let mut other_tasks: Vec<(DynFuture, Arc<AtomicBool>)> = Vec::new();
+        loop {
+            // Poll the main task and exit immediately if it's done.
+            if let Poll::Ready(result) = main_task.as_mut().poll(&mut context) {
+                return result;
+            }
+            // Poll other tasks and remove any that are Ready or have been aborted.
+            let is_pending = |(task, cancelled): &mut (DynFuture, Arc<AtomicBool>)| {
+                !cancelled.load(Ordering::Acquire) && task.as_mut().poll(&mut context).is_pending()
+            };
+            other_tasks.retain_mut(is_pending);
+            // Some tasks might have spawned new tasks. Pop from new_tasks until it's empty. Note
+            // that we can't use while-let here, because that would keep new_tasks locked in the
+            // loop body. See https://fasterthanli.me/articles/a-rust-match-made-in-hell.
+            loop {
+                let Some((mut task, cancelled)) = self.new_tasks.lock().unwrap().pop() else {
+                    break;
+                };
+                if cancelled.load(Ordering::Acquire) {
+                    continue; // Aborted before it was ever polled once.
+                }
+                // Poll each new task now, instead of waiting for the next iteration of the main
+                // loop, to let them register wakeups. Drop the ones that return Ready. This poll
+                // can also spawn more tasks, so it's important that new_tasks isn't locked here.
+                if task.as_mut().poll(&mut context).is_pending() {
+                    other_tasks.push((task, cancelled));
+                }
+            }
+            // Some tasks might wake other tasks. Re-poll if the AwakeFlag has been set. Polling
+            // futures that aren't ready yet is inefficient but allowed.
+            if awake_flag.check_and_clear() {
+                continue;
+            }
+            // Sleep until the next Waker is scheduled and then invoke Wakers that are ready.
+            let mut wake_times = self.wake_times.lock().unwrap();
+            let next_wake = wake_times.keys().next().expect("sleep forever?");
+            thread::sleep(next_wake.saturating_duration_since(Instant::now()));
+            while let Some(entry) = wake_times.first_entry() {
+                if *entry.key() <= Instant::now() {
+                    entry.remove().into_iter().for_each(Waker::wake);
+                } else {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// The Runtime that `spawn` and `sleep` reach for when called from inside a task, found via this
+// thread-local instead of a process-global static. It's only set while some Runtime is actually
+// driving `block_on`, so spawning from outside a runtime still panics, same as before.
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<Runtime>>> = const { RefCell::new(None) };
+}
+
+fn current_runtime() -> Arc<Runtime> {
+    CURRENT.with_borrow(|current| {
+        Arc::clone(
+            current
+                .as_ref()
+                .expect("spawn/sleep called outside of Runtime::block_on"),
+        )
+    })
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    current_runtime().spawn(future)
+}
+
+struct Sleep {
+    runtime: Arc<Runtime>,
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            self.runtime
+                .register_wake_time(self.wake_time, context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    Sleep {
+        runtime: current_runtime(),
+        wake_time: Instant::now() + duration,
+    }
+}
+
+const MAX_BLOCKING_THREADS: usize = 512;
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// A small growable pool of plain OS threads for blocking/CPU-bound work. Unlike Runtime, this
+// pool isn't tied to any one runtime: it's a single process-wide static, same as a real `spawn_
+// blocking` would be, since handing off blocking work doesn't need anything a Runtime owns.
+struct BlockingPool {
+    sender: Sender<Box<dyn FnOnce() + Send>>,
+    receiver: Receiver<Box<dyn FnOnce() + Send>>,
+    idle_threads: AtomicUsize,
+    total_threads: Mutex<usize>,
+}
+
+fn blocking_pool() -> &'static BlockingPool {
+    static POOL: OnceLock<BlockingPool> = OnceLock::new();
+    POOL.get_or_init(|| {
+        let (sender, receiver) = unbounded();
+        BlockingPool {
+            sender,
+            receiver,
+            idle_threads: AtomicUsize::new(0),
+            total_threads: Mutex::new(0),
+        }
+    })
+}
+
+impl BlockingPool {
+    fn spawn_job(&'static self, job: Box<dyn FnOnce() + Send>) {
+        self.sender.send(job).expect("pool receiver never dropped");
+        // If no thread is currently idle, grow the pool (up to the cap) so this job, and whatever
+        // else piles up behind it, doesn't wait on a thread that might be busy for a while.
+        if self.idle_threads.load(Ordering::Acquire) == 0 {
+            let mut total = self.total_threads.lock().unwrap();
+            if *total < MAX_BLOCKING_THREADS {
+                *total += 1;
+                self.spawn_thread();
+            }
+        }
+    }
+
+    fn spawn_thread(&'static self) {
+        thread::spawn(move || {
+            loop {
+                self.idle_threads.fetch_add(1, Ordering::AcqRel);
+                let job = self.receiver.recv_timeout(IDLE_TIMEOUT);
+                self.idle_threads.fetch_sub(1, Ordering::AcqRel);
+                match job {
+                    Ok(job) => job(),
+                    Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            *self.total_threads.lock().unwrap() -= 1;
+        });
+    }
+}
+
+fn spawn_blocking<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    // XXX: Once `f` has been handed to a pool thread, there's no way to actually interrupt it
+    // short of killing the thread. `cancelled` isn't even consulted here; calling `.abort()` on
+    // this handle just stops us from caring about the result.
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&join_state),
+        cancelled: Arc::clone(&cancelled),
+    };
+    let job = move || {
+        let value = f();
+        let mut guard = join_state.lock().unwrap();
+        if let JoinState::Awaited(waker) = mem::replace(&mut *guard, JoinState::Ready(value)) {
+            waker.wake();
+        }
+    };
+    blocking_pool().spawn_job(Box::new(job));
+    join_handle
+}
+
+#[derive(Debug)]
+struct Canceled;
+
+impl fmt::Display for Canceled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the sender was dropped without sending a value")
+    }
+}
+
+impl std::error::Error for Canceled {}
+
+// A single value handed from one task to another, independent of any Runtime: it's driven purely
+// by Wakers, the same as JoinHandle above.
+mod oneshot {
+    use super::*;
+
+    enum State<T> {
+        Unawaited,
+        Awaited(Waker),
+        Ready(T),
+        Closed,
+        Done,
+    }
+
+    struct Shared<T> {
+        state: Mutex<State<T>>,
+    }
+
+    pub struct Sender<T> {
+        shared: Arc<Shared<T>>,
+    }
+
+    pub struct Receiver<T> {
+        shared: Arc<Shared<T>>,
+    }
+
+    pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::Unawaited),
+        });
+        (
+            Sender {
+                shared: Arc::clone(&shared),
+            },
+            Receiver { shared },
+        )
+    }
+
+    impl<T> Sender<T> {
+        pub fn send(self, value: T) {
+            let mut guard = self.shared.state.lock().unwrap();
+            if let State::Awaited(waker) = &*guard {
+                waker.wake_by_ref();
+            }
+            *guard = State::Ready(value);
+        }
+    }
+
+    impl<T> Drop for Sender<T> {
+        fn drop(&mut self) {
+            let mut guard = self.shared.state.lock().unwrap();
+            // If `send` already ran, the state is Ready (or Done), and this is a no-op.
+            match mem::replace(&mut *guard, State::Closed) {
+                State::Awaited(waker) => waker.wake(),
+                State::Unawaited => {}
+                other => *guard = other,
+            }
+        }
+    }
+
+    impl<T> Future for Receiver<T> {
+        type Output = Result<T, Canceled>;
+
+        fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Result<T, Canceled>> {
+            let mut guard = self.shared.state.lock().unwrap();
+            match mem::replace(&mut *guard, State::Done) {
+                State::Ready(value) => Poll::Ready(Ok(value)),
+                State::Closed => Poll::Ready(Err(Canceled)),
+                State::Unawaited | State::Awaited(_) => {
+                    *guard = State::Awaited(context.waker().clone());
+                    Poll::Pending
+                }
+                State::Done => unreachable!("polled again after Ready or Closed"),
+            }
+        }
+    }
+}
+
+// A bounded multi-producer single-consumer queue, also Runtime-independent.
+mod mpsc {
+    use super::*;
+
+    struct Shared<T> {
+        queue: Mutex<VecDeque<T>>,
+        capacity: usize,
+        // Sender is Clone (multi-producer), so more than one Send can be blocked on a full queue
+        // at once; a single Option<Waker> slot would let a later sender's registration clobber an
+        // earlier one's, starving it forever even after capacity frees up.
+        send_wakers: Mutex<Vec<Waker>>,
+        recv_waker: Mutex<Option<Waker>>,
+        senders: Mutex<usize>,
+    }
+
+    pub struct Sender<T> {
+        shared: Arc<Shared<T>>,
+    }
+
+    pub struct Receiver<T> {
+        shared: Arc<Shared<T>>,
+    }
+
+    pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(VecDeque::new()),
+            capacity,
+            send_wakers: Mutex::new(Vec::new()),
+            recv_waker: Mutex::new(None),
+            senders: Mutex::new(1),
+        });
+        (
+            Sender {
+                shared: Arc::clone(&shared),
+            },
+            Receiver { shared },
+        )
+    }
+
+    impl<T> Clone for Sender<T> {
+        fn clone(&self) -> Self {
+            *self.shared.senders.lock().unwrap() += 1;
+            Sender {
+                shared: Arc::clone(&self.shared),
+            }
+        }
+    }
+
+    impl<T> Sender<T> {
+        pub fn send(&self, value: T) -> Send<'_, T> {
+            Send {
+                shared: &self.shared,
+                value: Some(value),
+            }
+        }
+    }
+
+    impl<T> Drop for Sender<T> {
+        fn drop(&mut self) {
+            let mut senders = self.shared.senders.lock().unwrap();
+            *senders -= 1;
+            if *senders == 0 {
+                if let Some(waker) = self.shared.recv_waker.lock().unwrap().take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+
+    pub struct Send<'a, T> {
+        shared: &'a Shared<T>,
+        value: Option<T>,
+    }
+
+    impl<'a, T> Future for Send<'a, T> {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+            let this = self.get_mut();
+            let mut queue = this.shared.queue.lock().unwrap();
+            if queue.len() < this.shared.capacity {
+                queue.push_back(this.value.take().expect("polled again after Ready"));
+                if let Some(waker) = this.shared.recv_waker.lock().unwrap().take() {
+                    waker.wake();
+                }
+                Poll::Ready(())
+            } else {
+                // Register this poll's waker unless we're already registered (e.g. this same Send
+                // was polled again before the queue had room); will_wake lets that check hold even
+                // though the waker handed to us may be a fresh clone each time.
+                let mut send_wakers = this.shared.send_wakers.lock().unwrap();
+                let waker = context.waker();
+                if !send_wakers.iter().any(|w| w.will_wake(waker)) {
+                    send_wakers.push(waker.clone());
+                }
+                Poll::Pending
+            }
+        }
+    }
+
+    impl<T> Future for Receiver<T> {
+        // None means every Sender has been dropped and the queue is empty: the channel is closed.
+        type Output = Option<T>;
+
+        fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<T>> {
+            let mut queue = self.shared.queue.lock().unwrap();
+            if let Some(value) = queue.pop_front() {
+                // Only one slot just freed up, but any number of Sends could be waiting on it;
+                // wake all of them and let them race to claim it, same as a condvar broadcast.
+                self.shared.send_wakers.lock().unwrap().drain(..).for_each(Waker::wake);
+                Poll::Ready(Some(value))
+            } else if *self.shared.senders.lock().unwrap() == 0 {
+                Poll::Ready(None)
+            } else {
+                *self.shared.recv_waker.lock().unwrap() = Some(context.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+async fn accept(listener: &mut TcpListener) -> io::Result<(TcpStream, SocketAddr)> {
+    std::future::poll_fn(|context| match listener.accept() {
+        Ok((stream, addr)) => {
+            stream.set_nonblocking(true)?;
+            Poll::Ready(Ok((stream, addr)))
+        }
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+            // TODO: This is a busy loop.
+            context.waker().wake_by_ref();
+            Poll::Pending
+        }
+        Err(e) => Poll::Ready(Err(e)),
+    })
+    .await
+}
+
+async fn write_all(mut buf: &[u8], stream: &mut TcpStream) -> io::Result<()> {
+    std::future::poll_fn(|context| {
+        while !buf.is_empty() {
+            match stream.write(buf) {
+                Ok(n) if n == 0 => {
+                    let e = io::Error::from(io::ErrorKind::WriteZero);
+                    return Poll::Ready(Err(e));
+                }
+                Ok(n) => buf = &buf[n..],
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    // TODO: This is a busy loop.
+                    context.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+        Poll::Ready(Ok(()))
+    })
+    .await
+}
+
+async fn compute_busy_checksum(n: u64) -> u64 {
+    spawn_blocking(move || {
+        thread::sleep(Duration::from_millis(50));
+        n.wrapping_mul(2654435761)
+    })
+    .await
+    .expect("blocking task was aborted")
+}
+
+async fn one_response(mut socket: TcpStream, n: u64) -> io::Result<()> {
+    // Using format! instead of write! avoids breaking up lines across multiple writes. This is
+    // easier than doing line buffering on the client side.
+    let start_msg = format!("start {n}\n");
+    write_all(start_msg.as_bytes(), &mut socket).await?;
+    sleep(Duration::from_secs(1)).await;
+    let checksum = compute_busy_checksum(n).await;
+    let end_msg = format!("end {n} checksum {checksum}\n");
+    write_all(end_msg.as_bytes(), &mut socket).await?;
+    Ok(())
+}
+
+async fn worker_main(receiver: Arc<Mutex<mpsc::Receiver<(u64, TcpStream)>>>) {
+    loop {
+        let next =
+            std::future::poll_fn(|context| Pin::new(&mut *receiver.lock().unwrap()).poll(context))
+                .await;
+        let Some((n, socket)) = next else {
+            return;
+        };
+        one_response(socket, n).await.unwrap();
+    }
+}
+
+async fn server_main(
+    mut listener: TcpListener,
+    sender: mpsc::Sender<(u64, TcpStream)>,
+) -> io::Result<()> {
+    let mut n = 1;
+    loop {
+        let (socket, _) = accept(&mut listener).await?;
+        sender.send((n, socket)).await;
+        n += 1;
+    }
+}
+
+async fn client_main() -> io::Result<()> {
+    // XXX: Assume that connect() returns quickly.
+    let mut socket = TcpStream::connect("localhost:8000")?;
+    socket.set_nonblocking(true)?;
+    let mut buf = [0; 1024];
+    loop {
+        let n = std::future::poll_fn(|context| match socket.read(&mut buf) {
+            Ok(n) => Poll::Ready(Ok(n)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                context.waker().wake_by_ref();
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        })
+        .await?;
+        if n == 0 {
+            return Ok(());
+        }
+        io::stdout().write_all(&buf[..n])?;
+    }
+}
+
+async fn async_main() -> io::Result<()> {
+    // Avoid a race between bind and connect by binding first.
+    let listener = TcpListener::bind("0.0.0.0:8000")?;
+    listener.set_nonblocking(true)?;
+    let (sender, receiver) = mpsc::channel(16);
+    let receiver = Arc::new(Mutex::new(receiver));
+    let mut worker_handles = Vec::new();
+    for _ in 0..4 {
+        worker_handles.push(spawn(worker_main(Arc::clone(&receiver))));
+    }
+    let (server_done_sender, server_done_receiver) = oneshot::channel();
+    let server_handle = spawn(async move {
+        let result = server_main(listener, sender).await;
+        server_done_sender.send(result);
+    });
+    // Run ten clients as ten different tasks.
+    let mut task_handles = Vec::new();
+    for _ in 1..=10 {
+        task_handles.push(spawn(client_main()));
+    }
+    for handle in task_handles {
+        handle.await.expect("client task was aborted")?;
+    }
+    // The server task would otherwise run forever; abort it now that every client is done, and
+    // drop its oneshot receiver, since a Closed result is expected in that case.
+    server_handle.abort();
+    drop(server_done_receiver);
+    for handle in worker_handles {
+        handle.await.expect("worker task was aborted");
+    }
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    let runtime = Runtime::new();
+    runtime.block_on(async_main())
+}
@@ -0,0 +1,441 @@
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+use crossbeam_utils::sync::{Parker, Unparker};
+use slab::Slab;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static WAKE_TIMES: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            let wakers_vec = wake_times.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+// The global injector is where `spawn` puts brand new tasks, and also where a woken task lands if
+// we don't know (or don't bother figuring out) which worker it last ran on. Each worker drains
+// its own local deque first, then the injector, then finally tries to steal from a sibling.
+static INJECTOR: OnceLock<Injector<Arc<Task>>> = OnceLock::new();
+static STEALERS: OnceLock<Vec<Stealer<Arc<Task>>>> = OnceLock::new();
+// One Unparker per worker thread, so a wakeup can nudge an idle worker awake instead of waiting
+// for it to notice on its own.
+static UNPARKERS: OnceLock<Vec<Unparker>> = OnceLock::new();
+
+fn injector() -> &'static Injector<Arc<Task>> {
+    INJECTOR.get_or_init(Injector::new)
+}
+
+struct Task {
+    future: Mutex<Option<DynFuture>>,
+    // Guards against double-enqueueing a task that's woken more than once before it's next
+    // polled. Cleared right before polling, so a wakeup that arrives *during* that poll still
+    // results in exactly one re-enqueue.
+    scheduled: AtomicBool,
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.scheduled.swap(true, Ordering::AcqRel) {
+            injector().push(Arc::clone(self));
+            if let Some(unparkers) = UNPARKERS.get() {
+                for unparker in unparkers {
+                    unparker.unpark();
+                }
+            }
+        }
+    }
+}
+
+fn poll_task(task: &Arc<Task>, context: &mut Context) {
+    task.scheduled.store(false, Ordering::Release);
+    let mut future_slot = task.future.lock().unwrap();
+    // The future is already gone if the task was woken during the poll that finished it.
+    let Some(future) = future_slot.as_mut() else {
+        return;
+    };
+    if future.as_mut().poll(context).is_ready() {
+        *future_slot = None;
+    }
+}
+
+enum JoinState<T> {
+    Unawaited,
+    Awaited(Waker),
+    Ready(T),
+    Done,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut guard = self.state.lock().unwrap();
+        // Use JoinState::Done as a placeholder, to take ownership of T.
+        match mem::replace(&mut *guard, JoinState::Done) {
+            JoinState::Ready(value) => Poll::Ready(value),
+            JoinState::Unawaited | JoinState::Awaited(_) => {
+                // Replace the previous Waker, if any. We only need the most recent one.
+                *guard = JoinState::Awaited(context.waker().clone());
+                Poll::Pending
+            }
+            JoinState::Done => unreachable!("polled again after Ready"),
+        }
+    }
+}
+
+async fn wrap_with_join_state<F: Future>(future: F, join_state: Arc<Mutex<JoinState<F::Output>>>) {
+    let value = future.await;
+    let mut guard = join_state.lock().unwrap();
+    if let JoinState::Awaited(waker) = &*guard {
+        waker.wake_by_ref();
+    }
+    *guard = JoinState::Ready(value)
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&join_state),
+    };
+    let wrapped = Box::pin(wrap_with_join_state(future, join_state));
+    let task = Arc::new(Task {
+        future: Mutex::new(Some(wrapped)),
+        scheduled: AtomicBool::new(true),
+    });
+    injector().push(task);
+    if let Some(unparkers) = UNPARKERS.get() {
+        for unparker in unparkers {
+            unparker.unpark();
+        }
+    }
+    join_handle
+}
+
+struct Entry {
+    readable: Mutex<Option<Waker>>,
+    writable: Mutex<Option<Waker>>,
+}
+
+// Same epoll-based reactor as client_server_reactor.rs; see that file for the design notes.
+struct Reactor {
+    epoll_fd: RawFd,
+    entries: Mutex<Slab<Arc<Entry>>>,
+}
+
+impl Reactor {
+    fn new() -> Reactor {
+        let epoll_fd = unsafe { libc::epoll_create1(0) };
+        if epoll_fd == -1 {
+            panic!("epoll_create1 failed: {}", io::Error::last_os_error());
+        }
+        Reactor {
+            epoll_fd,
+            entries: Mutex::new(Slab::new()),
+        }
+    }
+
+    fn register(&self, fd: RawFd) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let key = entries.insert(Arc::new(Entry {
+            readable: Mutex::new(None),
+            writable: Mutex::new(None),
+        }));
+        let mut event = libc::epoll_event {
+            events: (libc::EPOLLIN | libc::EPOLLOUT | libc::EPOLLET) as u32,
+            u64: key as u64,
+        };
+        let result = unsafe { libc::epoll_ctl(self.epoll_fd, libc::EPOLL_CTL_ADD, fd, &mut event) };
+        if result == -1 {
+            panic!("epoll_ctl(ADD) failed: {}", io::Error::last_os_error());
+        }
+        key
+    }
+
+    fn deregister(&self, key: usize, fd: RawFd) {
+        let result = unsafe {
+            libc::epoll_ctl(self.epoll_fd, libc::EPOLL_CTL_DEL, fd, std::ptr::null_mut())
+        };
+        if result == -1 {
+            panic!("epoll_ctl(DEL) failed: {}", io::Error::last_os_error());
+        }
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    fn entry(&self, key: usize) -> Arc<Entry> {
+        Arc::clone(&self.entries.lock().unwrap()[key])
+    }
+
+    fn wait(&self, timeout_ms: libc::c_int) {
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; 1024];
+        let num_events = unsafe {
+            libc::epoll_wait(self.epoll_fd, events.as_mut_ptr(), events.len() as libc::c_int, timeout_ms)
+        };
+        if num_events == -1 {
+            panic!("epoll_wait failed: {}", io::Error::last_os_error());
+        }
+        let entries = self.entries.lock().unwrap();
+        for event in &events[..num_events as usize] {
+            let Some(entry) = entries.get(event.u64 as usize) else {
+                continue;
+            };
+            if event.events & (libc::EPOLLIN | libc::EPOLLHUP | libc::EPOLLERR) as u32 != 0 {
+                if let Some(waker) = entry.readable.lock().unwrap().take() {
+                    waker.wake();
+                }
+            }
+            if event.events & (libc::EPOLLOUT | libc::EPOLLHUP | libc::EPOLLERR) as u32 != 0 {
+                if let Some(waker) = entry.writable.lock().unwrap().take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+fn reactor() -> &'static Reactor {
+    static REACTOR: OnceLock<Reactor> = OnceLock::new();
+    REACTOR.get_or_init(Reactor::new)
+}
+
+async fn accept(listener: &mut TcpListener) -> io::Result<(TcpStream, SocketAddr)> {
+    let key = reactor().register(listener.as_raw_fd());
+    let result = std::future::poll_fn(|context| match listener.accept() {
+        Ok((stream, addr)) => {
+            let result = stream.set_nonblocking(true);
+            Poll::Ready(result.and(Ok((stream, addr))))
+        }
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+            *reactor().entry(key).readable.lock().unwrap() = Some(context.waker().clone());
+            Poll::Pending
+        }
+        Err(e) => Poll::Ready(Err(e)),
+    })
+    .await;
+    reactor().deregister(key, listener.as_raw_fd());
+    result
+}
+
+async fn write_all(mut buf: &[u8], stream: &mut TcpStream) -> io::Result<()> {
+    let key = reactor().register(stream.as_raw_fd());
+    let result = std::future::poll_fn(|context| {
+        while !buf.is_empty() {
+            match stream.write(buf) {
+                Ok(n) if n == 0 => {
+                    let e = io::Error::from(io::ErrorKind::WriteZero);
+                    return Poll::Ready(Err(e));
+                }
+                Ok(n) => buf = &buf[n..],
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    *reactor().entry(key).writable.lock().unwrap() = Some(context.waker().clone());
+                    return Poll::Pending;
+                }
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+        Poll::Ready(Ok(()))
+    })
+    .await;
+    reactor().deregister(key, stream.as_raw_fd());
+    result
+}
+
+async fn print_all(stream: &mut TcpStream) -> io::Result<()> {
+    let key = reactor().register(stream.as_raw_fd());
+    let mut buf = [0; 1024];
+    let result = std::future::poll_fn(|context| {
+        // Edge-triggered: keep reading until WouldBlock, or we'd miss bytes that arrived after
+        // the last readiness notification.
+        loop {
+            match stream.read(&mut buf) {
+                Ok(n) if n == 0 => return Poll::Ready(Ok(())), // EOF
+                Ok(n) => io::stdout().write_all(&buf[..n])?,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    *reactor().entry(key).readable.lock().unwrap() = Some(context.waker().clone());
+                    return Poll::Pending;
+                }
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    })
+    .await;
+    reactor().deregister(key, stream.as_raw_fd());
+    result
+}
+
+async fn one_response(mut socket: TcpStream, n: u64) -> io::Result<()> {
+    let start_msg = format!("start {n}\n");
+    write_all(start_msg.as_bytes(), &mut socket).await?;
+    sleep(Duration::from_secs(1)).await;
+    let end_msg = format!("end {n}\n");
+    write_all(end_msg.as_bytes(), &mut socket).await?;
+    Ok(())
+}
+
+async fn server_main(mut listener: TcpListener) -> io::Result<()> {
+    let mut n = 1;
+    loop {
+        let (socket, _) = accept(&mut listener).await?;
+        spawn(async move { one_response(socket, n).await.unwrap() });
+        n += 1;
+    }
+}
+
+async fn client_main() -> io::Result<()> {
+    // XXX: Assume that connect() returns quickly.
+    let mut socket = TcpStream::connect("localhost:8000")?;
+    socket.set_nonblocking(true)?;
+    print_all(&mut socket).await?;
+    Ok(())
+}
+
+async fn async_main() -> io::Result<()> {
+    // Avoid a race between bind and connect by binding first.
+    let listener = TcpListener::bind("0.0.0.0:8000")?;
+    listener.set_nonblocking(true)?;
+    // Start the server on a background task.
+    spawn(async { server_main(listener).await.unwrap() });
+    // Run ten clients as ten different tasks.
+    let mut task_handles = Vec::new();
+    for _ in 1..=10 {
+        task_handles.push(spawn(client_main()));
+    }
+    for handle in task_handles {
+        handle.await?;
+    }
+    Ok(())
+}
+
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+// Each worker pops from its own local deque first, then the shared injector (taking a whole
+// batch at once to amortize the lock), and only then tries to steal from a sibling. This is the
+// same three-tier search smol and Tokio's multi-threaded scheduler both use.
+fn find_task(local: &Worker<Arc<Task>>) -> Option<Arc<Task>> {
+    local.pop().or_else(|| {
+        std::iter::repeat_with(|| {
+            injector()
+                .steal_batch_and_pop(local)
+                .or_else(|| STEALERS.get().unwrap().iter().map(Stealer::steal).collect())
+        })
+        .find(|s| !s.is_retry())
+        .and_then(Steal::success)
+    })
+}
+
+fn worker_loop(local: Worker<Arc<Task>>, parker: Parker) {
+    let waker_of = |task: &Arc<Task>| Waker::from(Arc::clone(task));
+    while !SHUTDOWN.load(Ordering::Acquire) {
+        match find_task(&local) {
+            Some(task) => {
+                let waker = waker_of(&task);
+                let mut context = Context::from_waker(&waker);
+                poll_task(&task, &mut context);
+            }
+            None => parker.park_timeout(Duration::from_millis(10)),
+        }
+    }
+}
+
+// A dedicated thread that owns the reactor wait loop and the sleep timers, since epoll_wait and
+// worker polling don't mix well on the same thread (one would have to interrupt the other).
+fn reactor_loop() {
+    while !SHUTDOWN.load(Ordering::Acquire) {
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        let timeout_ms = if let Some(time) = wake_times.keys().next() {
+            let duration = time.saturating_duration_since(Instant::now());
+            duration.as_millis().min(100) as libc::c_int
+        } else {
+            100 // wake up periodically anyway, to notice SHUTDOWN
+        };
+        drop(wake_times);
+        reactor().wait(timeout_ms);
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        while let Some(entry) = wake_times.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    let num_workers = num_cpus::get();
+    let workers: Vec<Worker<Arc<Task>>> = (0..num_workers).map(|_| Worker::new_fifo()).collect();
+    STEALERS
+        .set(workers.iter().map(Worker::stealer).collect())
+        .unwrap();
+    let parkers: Vec<Parker> = (0..num_workers).map(|_| Parker::new()).collect();
+    UNPARKERS
+        .set(parkers.iter().map(Parker::unparker).cloned().collect())
+        .unwrap();
+
+    let worker_threads: Vec<_> = workers
+        .into_iter()
+        .zip(parkers)
+        .map(|(local, parker)| thread::spawn(move || worker_loop(local, parker)))
+        .collect();
+    let reactor_thread = thread::spawn(reactor_loop);
+
+    // A one-slot channel just to get the final io::Result back out to the main thread.
+    let (result_sender, result_receiver) = crossbeam_channel::bounded(1);
+    spawn(async move {
+        let result = async_main().await;
+        result_sender.send(result).expect("main thread is waiting");
+    });
+    let result = result_receiver.recv().expect("async_main task panicked");
+
+    SHUTDOWN.store(true, Ordering::Release);
+    for unparker in UNPARKERS.get().unwrap() {
+        unparker.unpark();
+    }
+    for handle in worker_threads {
+        handle.join().expect("worker thread panicked");
+    }
+    reactor_thread.join().expect("reactor thread panicked");
+    result
+}
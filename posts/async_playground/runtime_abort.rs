@@ -0,0 +1,210 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static WAKERS: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wakers_tree = WAKERS.lock().unwrap();
+            let wakers_vec = wakers_tree.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+// Same per-task id/ready-queue scheduler as runtime_ready_queue.rs. Giving every task a stable id
+// is also exactly what makes abort() simple: it can remove the task straight out of TASKS by id,
+// rather than tasks_abort.rs's approach of threading an AtomicBool + on_abort callback through
+// every task.
+static TASKS: Mutex<BTreeMap<u64, DynFuture>> = Mutex::new(BTreeMap::new());
+static READY_QUEUE: Mutex<VecDeque<u64>> = Mutex::new(VecDeque::new());
+static NEXT_ID: Mutex<u64> = Mutex::new(0);
+
+struct TaskWaker {
+    id: u64,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        READY_QUEUE.lock().unwrap().push_back(self.id);
+    }
+}
+
+#[derive(Debug)]
+struct Aborted;
+
+impl fmt::Display for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("task was aborted")
+    }
+}
+
+impl std::error::Error for Aborted {}
+
+enum JoinState<T> {
+    Unawaited,
+    Awaited(Waker),
+    Ready(T),
+    Aborted,
+    Done,
+}
+
+struct JoinHandle<T> {
+    id: u64,
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, Aborted>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Result<T, Aborted>> {
+        let mut guard = self.state.lock().unwrap();
+        match mem::replace(&mut *guard, JoinState::Done) {
+            JoinState::Ready(value) => Poll::Ready(Ok(value)),
+            JoinState::Aborted => Poll::Ready(Err(Aborted)),
+            JoinState::Unawaited | JoinState::Awaited(_) => {
+                *guard = JoinState::Awaited(context.waker().clone());
+                Poll::Pending
+            }
+            JoinState::Done => unreachable!("polled again after Ready or Aborted"),
+        }
+    }
+}
+
+impl<T> JoinHandle<T> {
+    // Removing the task from TASKS by id is what makes this both race-proof and idempotent.
+    // - If the task already ran to completion, it's already gone from TASKS (the scheduler only
+    //   reinserts a task that polled Pending), so this removal fails and the Ready value already
+    //   stored in `state` is left alone for the handle's own poll to deliver.
+    // - If the task is merely queued or mid-sleep, this removal succeeds, and nothing will ever
+    //   poll it again.
+    // - Calling abort() a second time finds nothing left to remove and is a no-op.
+    fn abort(&self) {
+        if TASKS.lock().unwrap().remove(&self.id).is_none() {
+            return;
+        }
+        let mut guard = self.state.lock().unwrap();
+        if let JoinState::Awaited(waker) = mem::replace(&mut *guard, JoinState::Aborted) {
+            waker.wake();
+        }
+    }
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let id = {
+        let mut next_id = NEXT_ID.lock().unwrap();
+        let id = *next_id;
+        *next_id += 1;
+        id
+    };
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    let join_handle = JoinHandle {
+        id,
+        state: Arc::clone(&join_state),
+    };
+    let task: DynFuture = Box::pin(async move {
+        let value = future.await;
+        let mut guard = join_state.lock().unwrap();
+        if let JoinState::Awaited(waker) = mem::replace(&mut *guard, JoinState::Ready(value)) {
+            waker.wake();
+        }
+    });
+    TASKS.lock().unwrap().insert(id, task);
+    READY_QUEUE.lock().unwrap().push_back(id);
+    join_handle
+}
+
+async fn job(n: u64) -> u64 {
+    sleep(Duration::from_secs(1)).await;
+    n * n
+}
+
+async fn async_main() {
+    let mut handles = Vec::new();
+    for n in 1..=5 {
+        handles.push(spawn(job(n)));
+    }
+    // Abort job 3 before it ever gets a chance to finish, and abort job 1 twice, to check that a
+    // repeat abort() is harmless.
+    handles[2].abort();
+    handles[0].abort();
+    handles[0].abort();
+
+    for (n, handle) in (1..=5).zip(handles) {
+        match handle.await {
+            Ok(result) => println!("job {n} finished with {result}"),
+            Err(Aborted) => println!("job {n} was aborted"),
+        }
+    }
+}
+
+fn main() {
+    let waker = futures::task::noop_waker();
+    let mut context = Context::from_waker(&waker);
+    let mut main_task = Box::pin(async_main());
+    loop {
+        if main_task.as_mut().poll(&mut context).is_ready() {
+            return;
+        }
+        loop {
+            let Some(id) = READY_QUEUE.lock().unwrap().pop_front() else {
+                break;
+            };
+            let Some(mut task) = TASKS.lock().unwrap().remove(&id) else {
+                // Already polled to completion and removed, or aborted; either way, nothing to do.
+                continue;
+            };
+            let waker = Waker::from(Arc::new(TaskWaker { id }));
+            let mut task_context = Context::from_waker(&waker);
+            if task.as_mut().poll(&mut task_context).is_pending() {
+                TASKS.lock().unwrap().insert(id, task);
+            }
+        }
+        let mut wakers_tree = WAKERS.lock().unwrap();
+        let Some(next_wake) = wakers_tree.keys().next().copied() else {
+            // Nothing is asleep. The main task isn't in the ready queue, so go back to the top
+            // and poll it: the tasks it's waiting on have either finished or been aborted.
+            continue;
+        };
+        thread::sleep(next_wake.saturating_duration_since(Instant::now()));
+        while let Some(entry) = wakers_tree.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
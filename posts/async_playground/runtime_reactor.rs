@@ -0,0 +1,377 @@
+use polling::{Event, Events, Poller};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::{Duration, Instant};
+
+static WAKERS: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wakers_tree = WAKERS.lock().unwrap();
+            let wakers_vec = wakers_tree.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+static NEW_TASKS: Mutex<Vec<DynFuture>> = Mutex::new(Vec::new());
+
+enum JoinState<T> {
+    Unawaited,
+    Awaited(Waker),
+    Ready(T),
+    Done,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut guard = self.state.lock().unwrap();
+        match mem::replace(&mut *guard, JoinState::Done) {
+            JoinState::Ready(value) => Poll::Ready(value),
+            JoinState::Unawaited | JoinState::Awaited(_) => {
+                *guard = JoinState::Awaited(context.waker().clone());
+                Poll::Pending
+            }
+            JoinState::Done => unreachable!("polled again after Ready"),
+        }
+    }
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&join_state),
+    };
+    let task = Box::pin(async move {
+        let value = future.await;
+        let mut guard = join_state.lock().unwrap();
+        if let JoinState::Awaited(waker) = mem::replace(&mut *guard, JoinState::Ready(value)) {
+            waker.wake();
+        }
+    });
+    NEW_TASKS.lock().unwrap().push(task);
+    join_handle
+}
+
+// In production we'd use AtomicBool instead of Mutex<bool>.
+struct AwakeFlag(Mutex<bool>);
+
+impl AwakeFlag {
+    fn check_and_clear(&self) -> bool {
+        let mut guard = self.0.lock().unwrap();
+        let check = *guard;
+        *guard = false;
+        check
+    }
+}
+
+impl Wake for AwakeFlag {
+    fn wake(self: Arc<Self>) {
+        *self.0.lock().unwrap() = true;
+    }
+}
+
+#[derive(Default)]
+struct Entry {
+    readable: Option<Waker>,
+    writable: Option<Waker>,
+}
+
+// WAKERS is a BTreeMap of timer deadlines; this is the same idea for socket readiness, backed by
+// the `polling` crate's cross-platform epoll/kqueue/IOCP wrapper instead of a hand-rolled
+// epoll_ctl/epoll_wait pair.
+struct Reactor {
+    poller: Poller,
+    entries: Mutex<HashMap<RawFd, Entry>>,
+}
+
+impl Reactor {
+    fn new() -> Reactor {
+        Reactor {
+            poller: Poller::new().expect("failed to create poller"),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn register(&self, fd: RawFd) {
+        self.entries.lock().unwrap().insert(fd, Entry::default());
+        // SAFETY: the fd stays registered only as long as its Async<T> lives, and Drop
+        // deregisters it before the fd itself is closed.
+        unsafe {
+            self.poller
+                .add(fd, Event::all(fd as usize))
+                .expect("poller.add failed");
+        }
+    }
+
+    fn deregister(&self, fd: RawFd) {
+        self.entries.lock().unwrap().remove(&fd);
+        self.poller.delete(fd).expect("poller.delete failed");
+    }
+
+    // Registers `waker` for the given direction and re-arms interest in that fd, since a fired
+    // event has to be explicitly re-requested before the next wait() will report it again.
+    fn want_readable(&self, fd: RawFd, waker: Waker) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.get_mut(&fd).expect("fd not registered").readable = Some(waker);
+        self.poller.modify(fd, Event::all(fd as usize)).expect("poller.modify failed");
+    }
+
+    fn want_writable(&self, fd: RawFd, waker: Waker) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.get_mut(&fd).expect("fd not registered").writable = Some(waker);
+        self.poller.modify(fd, Event::all(fd as usize)).expect("poller.modify failed");
+    }
+
+    fn wait(&self, timeout: Option<Duration>) {
+        let mut events = Events::new();
+        self.poller.wait(&mut events, timeout).expect("poller.wait failed");
+        let mut entries = self.entries.lock().unwrap();
+        for event in events.iter() {
+            let fd = event.key as RawFd;
+            let Some(entry) = entries.get_mut(&fd) else {
+                continue;
+            };
+            if event.readable {
+                if let Some(waker) = entry.readable.take() {
+                    waker.wake();
+                }
+            }
+            if event.writable {
+                if let Some(waker) = entry.writable.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+fn reactor() -> &'static Reactor {
+    static REACTOR: OnceLock<Reactor> = OnceLock::new();
+    REACTOR.get_or_init(Reactor::new)
+}
+
+trait AsyncRead {
+    fn poll_read(&mut self, context: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>>;
+}
+
+trait AsyncWrite {
+    fn poll_write(&mut self, context: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>>;
+}
+
+struct Async<T: AsRawFd> {
+    inner: T,
+}
+
+impl<T: AsRawFd> Async<T> {
+    fn new(io: T) -> io::Result<Async<T>> {
+        reactor().register(io.as_raw_fd());
+        Ok(Async { inner: io })
+    }
+
+    fn poll_readable<R>(
+        &self,
+        context: &mut Context,
+        mut op: impl FnMut(&T) -> io::Result<R>,
+    ) -> Poll<io::Result<R>> {
+        match op(&self.inner) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                reactor().want_readable(self.inner.as_raw_fd(), context.waker().clone());
+                Poll::Pending
+            }
+            result => Poll::Ready(result),
+        }
+    }
+
+    fn poll_writable<R>(
+        &self,
+        context: &mut Context,
+        mut op: impl FnMut(&T) -> io::Result<R>,
+    ) -> Poll<io::Result<R>> {
+        match op(&self.inner) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                reactor().want_writable(self.inner.as_raw_fd(), context.waker().clone());
+                Poll::Pending
+            }
+            result => Poll::Ready(result),
+        }
+    }
+}
+
+impl<T: AsRawFd> Drop for Async<T> {
+    fn drop(&mut self) {
+        reactor().deregister(self.inner.as_raw_fd());
+    }
+}
+
+impl Async<TcpListener> {
+    fn bind(address: &str) -> io::Result<Async<TcpListener>> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Async::new(listener)
+    }
+
+    async fn accept(&self) -> io::Result<(Async<TcpStream>, SocketAddr)> {
+        let (stream, addr) =
+            std::future::poll_fn(|context| self.poll_readable(context, |listener| listener.accept())).await?;
+        stream.set_nonblocking(true)?;
+        Ok((Async::new(stream)?, addr))
+    }
+}
+
+impl Async<TcpStream> {
+    fn connect(address: &str) -> io::Result<Async<TcpStream>> {
+        // XXX: Assume that connect() returns quickly.
+        let stream = TcpStream::connect(address)?;
+        stream.set_nonblocking(true)?;
+        Async::new(stream)
+    }
+}
+
+impl AsyncRead for Async<TcpStream> {
+    fn poll_read(&mut self, context: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        self.poll_readable(context, |stream| (&*stream).read(buf))
+    }
+}
+
+impl AsyncWrite for Async<TcpStream> {
+    fn poll_write(&mut self, context: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.poll_writable(context, |stream| (&*stream).write(buf))
+    }
+}
+
+async fn write_all(buf: &[u8], stream: &mut Async<TcpStream>) -> io::Result<()> {
+    let mut buf = buf;
+    while !buf.is_empty() {
+        let n = std::future::poll_fn(|context| stream.poll_write(context, buf)).await?;
+        if n == 0 {
+            return Err(io::Error::from(io::ErrorKind::WriteZero));
+        }
+        buf = &buf[n..];
+    }
+    Ok(())
+}
+
+async fn print_all(stream: &mut Async<TcpStream>) -> io::Result<()> {
+    let mut buf = [0; 1024];
+    loop {
+        let n = std::future::poll_fn(|context| stream.poll_read(context, &mut buf)).await?;
+        if n == 0 {
+            return Ok(()); // EOF
+        }
+        io::stdout().write_all(&buf[..n])?;
+    }
+}
+
+async fn one_response(mut socket: Async<TcpStream>, n: u64) -> io::Result<()> {
+    let start_msg = format!("start {n}\n");
+    write_all(start_msg.as_bytes(), &mut socket).await?;
+    sleep(Duration::from_secs(1)).await;
+    let end_msg = format!("end {n}\n");
+    write_all(end_msg.as_bytes(), &mut socket).await?;
+    Ok(())
+}
+
+async fn server_main(listener: Async<TcpListener>) -> io::Result<()> {
+    let mut n = 1;
+    loop {
+        let (socket, _) = listener.accept().await?;
+        spawn(async move { one_response(socket, n).await.unwrap() });
+        n += 1;
+    }
+}
+
+async fn client_main() -> io::Result<()> {
+    let mut socket = Async::<TcpStream>::connect("localhost:8000")?;
+    print_all(&mut socket).await?;
+    Ok(())
+}
+
+async fn async_main() -> io::Result<()> {
+    let listener = Async::<TcpListener>::bind("0.0.0.0:8000")?;
+    spawn(async { server_main(listener).await.unwrap() });
+    let mut task_handles = Vec::new();
+    for _ in 1..=10 {
+        task_handles.push(spawn(client_main()));
+    }
+    for handle in task_handles {
+        handle.await?;
+    }
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    let awake_flag = Arc::new(AwakeFlag(Mutex::new(false)));
+    let waker = Waker::from(Arc::clone(&awake_flag));
+    let mut context = Context::from_waker(&waker);
+    let mut main_task = Box::pin(async_main());
+    let mut other_tasks: Vec<DynFuture> = Vec::new();
+    loop {
+        if let Poll::Ready(result) = main_task.as_mut().poll(&mut context) {
+            return result;
+        }
+        let is_pending = |task: &mut DynFuture| task.as_mut().poll(&mut context).is_pending();
+        other_tasks.retain_mut(is_pending);
+        loop {
+            let Some(mut task) = NEW_TASKS.lock().unwrap().pop() else {
+                break;
+            };
+            if task.as_mut().poll(&mut context).is_pending() {
+                other_tasks.push(task);
+            }
+        }
+        if awake_flag.check_and_clear() {
+            continue;
+        }
+        // All tasks are either sleeping or blocked on IO. Wait on the reactor, using the earliest
+        // WAKERS entry (if any) as the timeout, instead of a plain thread::sleep.
+        let mut wakers_tree = WAKERS.lock().unwrap();
+        let timeout = wakers_tree
+            .keys()
+            .next()
+            .map(|time| time.saturating_duration_since(Instant::now()));
+        reactor().wait(timeout);
+        while let Some(entry) = wakers_tree.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static WAKERS: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wakers_tree = WAKERS.lock().unwrap();
+            let wakers_vec = wakers_tree.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+// The old main loop polled main_task and every entry in other_tasks on every wakeup, admitting in
+// its own comment that re-polling futures that aren't ready yet is inefficient. Here each task
+// gets a stable id and its own Waker; waking a task just drops its id onto READY_QUEUE, so the
+// main loop only ever touches tasks that actually have new work to do.
+static TASKS: Mutex<BTreeMap<u64, DynFuture>> = Mutex::new(BTreeMap::new());
+static READY_QUEUE: Mutex<VecDeque<u64>> = Mutex::new(VecDeque::new());
+static NEXT_ID: Mutex<u64> = Mutex::new(0);
+
+struct TaskWaker {
+    id: u64,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        READY_QUEUE.lock().unwrap().push_back(self.id);
+    }
+}
+
+enum JoinState<T> {
+    Unawaited,
+    Awaited(Waker),
+    Ready(T),
+    Done,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut guard = self.state.lock().unwrap();
+        // Use JoinState::Done as a placeholder, to take ownership of T.
+        match mem::replace(&mut *guard, JoinState::Done) {
+            JoinState::Ready(value) => Poll::Ready(value),
+            JoinState::Unawaited | JoinState::Awaited(_) => {
+                *guard = JoinState::Awaited(context.waker().clone());
+                Poll::Pending
+            }
+            JoinState::Done => unreachable!("polled again after Ready"),
+        }
+    }
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&join_state),
+    };
+    let task: DynFuture = Box::pin(async move {
+        let value = future.await;
+        let mut guard = join_state.lock().unwrap();
+        if let JoinState::Awaited(waker) = mem::replace(&mut *guard, JoinState::Ready(value)) {
+            waker.wake();
+        }
+    });
+    let id = {
+        let mut next_id = NEXT_ID.lock().unwrap();
+        let id = *next_id;
+        *next_id += 1;
+        id
+    };
+    TASKS.lock().unwrap().insert(id, task);
+    // A brand new task has never been polled, so it has to start in the queue to get its first
+    // poll at all; nothing else would ever wake it otherwise.
+    READY_QUEUE.lock().unwrap().push_back(id);
+    join_handle
+}
+
+async fn job(n: u64) -> u64 {
+    sleep(Duration::from_secs(1)).await;
+    println!("finished job {n}");
+    n * n
+}
+
+async fn async_main() {
+    println!("Spawn 10 tasks and wait for each one's result.\n");
+    let mut task_handles = Vec::new();
+    for n in 1..=10 {
+        task_handles.push(spawn(job(n)));
+    }
+    for handle in task_handles {
+        let result = handle.await;
+        println!("collected result {result}");
+    }
+}
+
+fn main() {
+    let waker = futures::task::noop_waker();
+    let mut context = Context::from_waker(&waker);
+    let mut main_task = Box::pin(async_main());
+    loop {
+        if main_task.as_mut().poll(&mut context).is_ready() {
+            return;
+        }
+        // Drain the ready queue, polling only the tasks that were actually woken, rather than
+        // every task that exists. A task might wake itself (or another task) while it's being
+        // polled, so this has to keep draining until the queue is empty, not just do one pass.
+        loop {
+            let Some(id) = READY_QUEUE.lock().unwrap().pop_front() else {
+                break;
+            };
+            let Some(mut task) = TASKS.lock().unwrap().remove(&id) else {
+                // The id was already polled to completion and removed; a task can be woken more
+                // than once before it's next polled, so a stale id in the queue is expected.
+                continue;
+            };
+            let waker = Waker::from(Arc::new(TaskWaker { id }));
+            let mut task_context = Context::from_waker(&waker);
+            if task.as_mut().poll(&mut task_context).is_pending() {
+                TASKS.lock().unwrap().insert(id, task);
+            }
+        }
+        // Every spawned task is either asleep or waiting on a Waker that hasn't fired yet. Sleep
+        // until the next WAKERS deadline and then invoke whichever Wakers are ready.
+        let mut wakers_tree = WAKERS.lock().unwrap();
+        let Some(next_wake) = wakers_tree.keys().next().copied() else {
+            // Nothing is asleep. The main task isn't in the ready queue, so go back to the top
+            // and poll it: the tasks it's waiting on may have just finished.
+            continue;
+        };
+        thread::sleep(next_wake.saturating_duration_since(Instant::now()));
+        while let Some(entry) = wakers_tree.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
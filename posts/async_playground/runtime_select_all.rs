@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static WAKE_TIMES: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            let wakers_vec = wake_times.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+// tasks_select_all.rs's select_all() takes futures the caller has already boxed as trait objects
+// and re-scans them from index 0 on every poll. This version is generic over one future type F,
+// boxes each future once in new() and keeps it boxed for the whole wait, and starts each scan
+// from a cursor that moves one branch further along after every Pending poll, so the branch that
+// gets polled first rotates instead of always being index 0.
+struct SelectAll<F: ?Sized> {
+    futures: Vec<Pin<Box<F>>>,
+    cursor: usize,
+}
+
+impl<T, F: Future<Output = T>> SelectAll<F> {
+    fn new(futures: impl IntoIterator<Item = F>) -> SelectAll<F> {
+        SelectAll {
+            futures: futures.into_iter().map(Box::pin).collect(),
+            cursor: 0,
+        }
+    }
+}
+
+impl<T, F: Future<Output = T>> Future for SelectAll<F> {
+    // The index is into the original Vec, before the winner is pulled out of `remaining`.
+    type Output = (usize, T, Vec<Pin<Box<F>>>);
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        // Safety: we never move out of `self` except to drain `futures` into the returned
+        // `remaining` Vec, which happens only once, right before returning Ready for good.
+        let this = unsafe { self.get_unchecked_mut() };
+        let len = this.futures.len();
+        for offset in 0..len {
+            let i = (this.cursor + offset) % len;
+            if let Poll::Ready(value) = this.futures[i].as_mut().poll(context) {
+                this.cursor = (i + 1) % len;
+                let remaining = this
+                    .futures
+                    .drain(..)
+                    .enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .map(|(_, future)| future)
+                    .collect();
+                return Poll::Ready((i, value, remaining));
+            }
+        }
+        // Nothing was ready: start the next scan one branch further along.
+        if len > 0 {
+            this.cursor = (this.cursor + 1) % len;
+        }
+        Poll::Pending
+    }
+}
+
+fn select_all<T, F: Future<Output = T>>(futures: impl IntoIterator<Item = F>) -> SelectAll<F> {
+    SelectAll::new(futures)
+}
+
+// A minimal hand-rolled Stream trait, the same idea as tasks_completion_stream.rs's poll_next but
+// for an unbounded sequence of items instead of a single eventual output.
+trait Stream {
+    type Item;
+    fn poll_next(&mut self, context: &mut Context) -> Poll<Option<Self::Item>>;
+}
+
+// select_all's streaming sibling: mirrors how an echo server loops `while let Some(conn) =
+// listeners.next()` over a runtime-sized set of listeners, yielding each item as it arrives and
+// quietly dropping any stream once it's exhausted.
+struct SelectAllStream<S> {
+    streams: Vec<S>,
+    cursor: usize,
+}
+
+impl<S: Stream> SelectAllStream<S> {
+    fn new(streams: impl IntoIterator<Item = S>) -> SelectAllStream<S> {
+        SelectAllStream {
+            streams: streams.into_iter().collect(),
+            cursor: 0,
+        }
+    }
+
+    fn poll_next(&mut self, context: &mut Context) -> Poll<Option<(usize, S::Item)>> {
+        while !self.streams.is_empty() {
+            let len = self.streams.len();
+            let mut found_exhausted = None;
+            for offset in 0..len {
+                let i = (self.cursor + offset) % len;
+                match self.streams[i].poll_next(context) {
+                    Poll::Ready(Some(item)) => {
+                        self.cursor = (i + 1) % len;
+                        return Poll::Ready(Some((i, item)));
+                    }
+                    Poll::Ready(None) => {
+                        found_exhausted = Some(i);
+                        break;
+                    }
+                    Poll::Pending => {}
+                }
+            }
+            match found_exhausted {
+                // Removing shifts every later index down by one, which is exactly why this has to
+                // restart the scan rather than resume at `offset + 1`.
+                Some(i) => {
+                    self.streams.remove(i);
+                    self.cursor = self.cursor.min(self.streams.len());
+                }
+                None => return Poll::Pending,
+            }
+        }
+        Poll::Ready(None)
+    }
+}
+
+impl<S: Stream> Future for &mut SelectAllStream<S> {
+    type Output = Option<(usize, S::Item)>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        Pin::into_inner(self).poll_next(context)
+    }
+}
+
+fn select_all_stream<S: Stream>(streams: impl IntoIterator<Item = S>) -> SelectAllStream<S> {
+    SelectAllStream::new(streams)
+}
+
+// A toy "listener": ticks `remaining` times, `interval` apart, then falls silent for good.
+struct Ticks {
+    interval: Duration,
+    next_tick: Instant,
+    remaining: u64,
+}
+
+impl Ticks {
+    fn new(interval: Duration, count: u64) -> Ticks {
+        Ticks {
+            interval,
+            next_tick: Instant::now() + interval,
+            remaining: count,
+        }
+    }
+}
+
+impl Stream for Ticks {
+    type Item = u64;
+
+    fn poll_next(&mut self, context: &mut Context) -> Poll<Option<u64>> {
+        if self.remaining == 0 {
+            return Poll::Ready(None);
+        }
+        if Instant::now() < self.next_tick {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            wake_times.entry(self.next_tick).or_default().push(context.waker().clone());
+            return Poll::Pending;
+        }
+        self.remaining -= 1;
+        self.next_tick += self.interval;
+        Poll::Ready(Some(self.remaining))
+    }
+}
+
+async fn slow_value(n: u64, delay_ms: u64) -> u64 {
+    sleep(Duration::from_millis(delay_ms)).await;
+    n
+}
+
+async fn async_main() {
+    let branches = vec![slow_value(1, 300), slow_value(2, 100), slow_value(3, 200)];
+    let (index, value, _remaining) = select_all(branches).await;
+    println!("select_all: branch {index} won first with {value}");
+
+    let mut ticking = select_all_stream(vec![
+        Ticks::new(Duration::from_millis(120), 3),
+        Ticks::new(Duration::from_millis(90), 2),
+    ]);
+    while let Some((index, remaining)) = (&mut ticking).await {
+        println!("select_all_stream: listener {index} ticked, {remaining} left");
+    }
+}
+
+fn main() {
+    let waker = futures::task::noop_waker();
+    let mut context = Context::from_waker(&waker);
+    let mut main_task = Box::pin(async_main());
+    loop {
+        if main_task.as_mut().poll(&mut context).is_ready() {
+            return;
+        }
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        let next_wake = wake_times.keys().next().expect("sleep forever?");
+        thread::sleep(next_wake.saturating_duration_since(Instant::now()));
+        while let Some(entry) = wake_times.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use futures::future;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_SLICE: Duration = Duration::from_millis(20);
+
+// Throttled deadlines, rounded up to the nearest slice boundary so that nearby Sleeps share a
+// BTreeMap key and wake together; see sleep_throttled.rs for the single-tick version this
+// generalizes. AFTER holds deadlines that must never fire early (a timeout, a deadline a caller is
+// relying on), so they're checked against the real clock instead of being rounded into a slice.
+static WAKE_TIMES: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+static AFTER: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            let wakers_vec = wake_times.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+struct SleepAfter {
+    wake_time: Instant,
+}
+
+impl Future for SleepAfter {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut after = AFTER.lock().unwrap();
+            let wakers_vec = after.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+// The slice duration trades latency for fewer wakeups: a larger slice means more unrelated Sleeps
+// land on the same boundary and wake the reactor together, but each one can fire up to one whole
+// slice late. `Throttle::new()` picks a reasonable default; `with_slice` is the builder hook for
+// callers who want to tune that tradeoff themselves.
+struct Throttle {
+    slice: Duration,
+    start: Instant,
+}
+
+impl Throttle {
+    fn new() -> Throttle {
+        Throttle::with_slice(DEFAULT_SLICE)
+    }
+
+    fn with_slice(slice: Duration) -> Throttle {
+        Throttle { slice, start: Instant::now() }
+    }
+
+    fn round_up(&self, wake_time: Instant) -> Instant {
+        let since_start = wake_time.saturating_duration_since(self.start);
+        let slices = since_start.as_nanos().div_ceil(self.slice.as_nanos());
+        self.start + self.slice * slices as u32
+    }
+
+    // Throttled: may fire up to one slice late, but batches with other Sleeps in the same slice.
+    fn sleep(&self, duration: Duration) -> Sleep {
+        let wake_time = self.round_up(Instant::now() + duration);
+        Sleep { wake_time }
+    }
+
+    // Strict: checked against the real clock, so throttling only ever delays other work sharing
+    // the reactor -- it can never make this fire early.
+    fn sleep_after(&self, duration: Duration) -> SleepAfter {
+        let wake_time = Instant::now() + duration;
+        SleepAfter { wake_time }
+    }
+}
+
+fn fire_due(tree: &mut BTreeMap<Instant, Vec<Waker>>, now: Instant) {
+    while let Some(entry) = tree.first_entry() {
+        if *entry.key() <= now {
+            entry.remove().into_iter().for_each(Waker::wake);
+        } else {
+            break;
+        }
+    }
+}
+
+async fn heartbeat(throttle: &Throttle, n: u64) {
+    println!("heartbeat {n} start");
+    throttle.sleep(Duration::from_millis(300 + 7 * n)).await;
+    println!("heartbeat {n} end");
+}
+
+async fn strict_timeout(throttle: &Throttle) {
+    println!("strict timeout start");
+    throttle.sleep_after(Duration::from_millis(333)).await;
+    println!("strict timeout end");
+}
+
+fn main() {
+    let throttle = Throttle::new();
+    let mut heartbeats = Vec::new();
+    for n in 1..=10 {
+        heartbeats.push(heartbeat(&throttle, n));
+    }
+    let mut joined_future = Box::pin(future::join(future::join_all(heartbeats), strict_timeout(&throttle)));
+    let waker = futures::task::noop_waker();
+    let mut context = Context::from_waker(&waker);
+    let mut wakeups = 0;
+    while joined_future.as_mut().poll(&mut context).is_pending() {
+        wakeups += 1;
+        let wake_times = WAKE_TIMES.lock().unwrap();
+        let after = AFTER.lock().unwrap();
+        let next_wake = [wake_times.keys().next(), after.keys().next()]
+            .into_iter()
+            .flatten()
+            .min()
+            .copied()
+            .expect("sleep forever?");
+        drop(wake_times);
+        drop(after);
+        thread::sleep(next_wake.saturating_duration_since(Instant::now()));
+        let now = Instant::now();
+        fire_due(&mut WAKE_TIMES.lock().unwrap(), now);
+        fire_due(&mut AFTER.lock().unwrap(), now);
+    }
+    // With ten heartbeats spread 7ms apart but rounded up to a 20ms slice, this prints a number
+    // much smaller than ten, while the strict timeout still lands close to its real 333ms mark.
+    println!("reactor woke up {wakeups} times");
+}
@@ -0,0 +1,265 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+static WAKERS: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wakers_tree = WAKERS.lock().unwrap();
+            let wakers_vec = wakers_tree.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+// Freshly spawned tasks land here first, with no id yet. A worker that finds both its local queue
+// and the injector empty drains this before it parks, which is what actually moves a task into
+// the shared store and onto the injector queue for some worker to claim.
+static NEW_TASKS: Mutex<Vec<DynFuture>> = Mutex::new(Vec::new());
+static TASKS: Mutex<BTreeMap<u64, DynFuture>> = Mutex::new(BTreeMap::new());
+static NEXT_ID: Mutex<u64> = Mutex::new(0);
+
+// The global injector and its condvar. A worker whose local queue runs dry steals a batch from
+// here; a worker that finds both empty parks on INJECTOR_CONDVAR until TaskWaker::wake_by_ref (or
+// a newly distributed task) notifies it.
+static INJECTOR: Mutex<VecDeque<u64>> = Mutex::new(VecDeque::new());
+static INJECTOR_CONDVAR: Condvar = Condvar::new();
+
+struct TaskWaker {
+    id: u64,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        INJECTOR.lock().unwrap().push_back(self.id);
+        INJECTOR_CONDVAR.notify_one();
+    }
+}
+
+enum JoinState<T> {
+    Unawaited,
+    Awaited(Waker),
+    Ready(T),
+    Done,
+}
+
+// Tasks can now be polled from any worker thread, so DynFuture already requires Send; that's what
+// makes it sound to share this same Arc<Mutex<JoinState<T>>> across threads without any further
+// synchronization than the Mutex itself.
+struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut guard = self.state.lock().unwrap();
+        match mem::replace(&mut *guard, JoinState::Done) {
+            JoinState::Ready(value) => Poll::Ready(value),
+            JoinState::Unawaited | JoinState::Awaited(_) => {
+                *guard = JoinState::Awaited(context.waker().clone());
+                Poll::Pending
+            }
+            JoinState::Done => unreachable!("polled again after Ready"),
+        }
+    }
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&join_state),
+    };
+    let task: DynFuture = Box::pin(async move {
+        let value = future.await;
+        let mut guard = join_state.lock().unwrap();
+        if let JoinState::Awaited(waker) = mem::replace(&mut *guard, JoinState::Ready(value)) {
+            waker.wake();
+        }
+    });
+    NEW_TASKS.lock().unwrap().push(task);
+    INJECTOR_CONDVAR.notify_one();
+    join_handle
+}
+
+// Gives every task in NEW_TASKS a stable id, moves it into TASKS, and drops its id onto the
+// injector for its first poll. Returns whether it found anything to do.
+fn distribute_new_tasks() -> bool {
+    let new_tasks = mem::take(&mut *NEW_TASKS.lock().unwrap());
+    if new_tasks.is_empty() {
+        return false;
+    }
+    let mut tasks = TASKS.lock().unwrap();
+    let mut injector = INJECTOR.lock().unwrap();
+    for task in new_tasks {
+        let id = {
+            let mut next_id = NEXT_ID.lock().unwrap();
+            let id = *next_id;
+            *next_id += 1;
+            id
+        };
+        tasks.insert(id, task);
+        injector.push_back(id);
+    }
+    INJECTOR_CONDVAR.notify_all();
+    true
+}
+
+fn poll_task(id: u64) {
+    let Some(mut task) = TASKS.lock().unwrap().remove(&id) else {
+        // Woken more than once before its next poll; the stale id is a no-op.
+        return;
+    };
+    let waker = Waker::from(Arc::new(TaskWaker { id }));
+    let mut context = Context::from_waker(&waker);
+    if task.as_mut().poll(&mut context).is_pending() {
+        TASKS.lock().unwrap().insert(id, task);
+    }
+}
+
+// Each worker checks its own local queue first, then steals a whole batch from the injector at
+// once to amortize the lock, and only parks once there's truly nothing anywhere.
+fn worker_loop(mut local: VecDeque<u64>) {
+    loop {
+        if let Some(id) = local.pop_front() {
+            poll_task(id);
+            continue;
+        }
+        let mut injector = INJECTOR.lock().unwrap();
+        if !injector.is_empty() {
+            mem::swap(&mut local, &mut *injector);
+            drop(injector);
+            continue;
+        }
+        drop(injector);
+        if distribute_new_tasks() {
+            continue;
+        }
+        let injector = INJECTOR.lock().unwrap();
+        let _ = INJECTOR_CONDVAR
+            .wait_timeout_while(injector, Duration::from_millis(50), |injector| injector.is_empty())
+            .unwrap();
+    }
+}
+
+// A dedicated thread to fire due timers, since no single worker owns WAKERS the way the
+// single-threaded executors elsewhere in this chunk do.
+fn timer_loop() {
+    loop {
+        let mut wakers_tree = WAKERS.lock().unwrap();
+        let timeout = if let Some(time) = wakers_tree.keys().next() {
+            time.saturating_duration_since(Instant::now()).min(Duration::from_millis(100))
+        } else {
+            Duration::from_millis(100)
+        };
+        drop(wakers_tree);
+        thread::sleep(timeout);
+        let mut wakers_tree = WAKERS.lock().unwrap();
+        while let Some(entry) = wakers_tree.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+// A Waker that just unparks block_on's thread, the same trick futures::executor::block_on uses:
+// the root future doesn't need its own queue entry, since there's only ever one of it and it's
+// driven inline on the calling thread.
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+struct Runtime;
+
+impl Runtime {
+    // Starts `worker_threads` worker threads plus one timer thread. None of them are ever joined;
+    // like the timer thread, they run for the lifetime of the process.
+    fn new(worker_threads: usize) -> Runtime {
+        for _ in 0..worker_threads {
+            thread::spawn(|| worker_loop(VecDeque::new()));
+        }
+        thread::spawn(timer_loop);
+        Runtime
+    }
+
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut context = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            match future.as_mut().poll(&mut context) {
+                Poll::Ready(value) => return value,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+}
+
+// Busy-waits for a bit instead of calling sleep().await: on a single-threaded executor this would
+// stall every other task, but spread across worker_threads it only ties up one of them.
+async fn job(n: u64) -> u64 {
+    let busy_start = Instant::now();
+    while Instant::now() < busy_start + Duration::from_millis(50) {}
+    sleep(Duration::from_millis(100)).await;
+    n * n
+}
+
+async fn async_main() {
+    let mut task_handles = Vec::new();
+    for n in 1..=20 {
+        task_handles.push(spawn(job(n)));
+    }
+    for handle in task_handles {
+        println!("{}", handle.await);
+    }
+}
+
+fn main() {
+    // Compare throughput against a single worker thread by changing this to Runtime::new(1), the
+    // same way the basketball demo sweeps THREAD_NUMS.
+    let runtime = Runtime::new(num_cpus::get());
+    runtime.block_on(async_main());
+}
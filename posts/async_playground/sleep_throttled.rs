@@ -0,0 +1,82 @@
+use futures::future;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Coalesce wakeups onto a fixed tick, instead of waking up at the exact instant every Sleep asked
+// for. Lots of independent timers (e.g. retry backoffs, heartbeats) end up sharing the same tick,
+// so the reactor thread wakes far less often. The tradeoff is that every Sleep can fire up to one
+// tick late, which is the same tradeoff Linux's timer wheel and Go's runtime timers make.
+const TICK: Duration = Duration::from_millis(50);
+
+fn round_up_to_tick(wake_time: Instant, start: Instant) -> Instant {
+    let since_start = wake_time.saturating_duration_since(start);
+    let ticks = since_start.as_nanos().div_ceil(TICK.as_nanos());
+    start + TICK * ticks as u32
+}
+
+static WAKE_TIMES: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            let wakers_vec = wake_times.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration, start: Instant) -> Sleep {
+    // Round the wake time up to the next tick boundary so that Sleeps requested at nearby times
+    // land on the same BTreeMap key and get woken together.
+    let wake_time = round_up_to_tick(Instant::now() + duration, start);
+    Sleep { wake_time }
+}
+
+async fn foo(n: u64, start: Instant) {
+    println!("start {n}");
+    sleep(Duration::from_millis(300 + 7 * n), start).await;
+    println!("end {n}");
+}
+
+fn main() {
+    let start = Instant::now();
+    let mut futures = Vec::new();
+    for n in 1..=10 {
+        futures.push(foo(n, start));
+    }
+    let mut joined_future = Box::pin(future::join_all(futures));
+    let waker = futures::task::noop_waker();
+    let mut context = Context::from_waker(&waker);
+    let mut wakeups = 0;
+    while joined_future.as_mut().poll(&mut context).is_pending() {
+        wakeups += 1;
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        let next_wake = wake_times.keys().next().expect("sleep forever?");
+        thread::sleep(next_wake.saturating_duration_since(Instant::now()));
+        while let Some(entry) = wake_times.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+    // With ten Sleeps spread 7ms apart but rounded up to a 50ms tick, this prints a number much
+    // smaller than ten.
+    println!("reactor woke up {wakeups} times");
+}
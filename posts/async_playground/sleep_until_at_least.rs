@@ -0,0 +1,77 @@
+use futures::future;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static WAKE_TIMES: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+// Unlike `sleep(duration)`, which computes its wake time from `Instant::now()` at the moment
+// it's called, `sleep_until_at_least` takes an absolute deadline. That's what you want for a
+// fixed schedule (e.g. "tick every second"), because computing each deadline from the *previous*
+// deadline instead of from "now" avoids drift: a slow poll doesn't push every later tick back.
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        // Re-check the clock on every poll, even a spurious one, rather than trusting that
+        // whoever woke us did so at or after `wake_time`. This is what actually makes the
+        // "at least" guarantee hold: the future can be polled early, but it can never resolve
+        // early.
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            let wakers_vec = wake_times.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep_until_at_least(wake_time: Instant) -> Sleep {
+    Sleep { wake_time }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    sleep_until_at_least(Instant::now() + duration)
+}
+
+async fn ticker(name: &str, period: Duration, count: u64) {
+    let mut next_tick = Instant::now() + period;
+    for i in 1..=count {
+        sleep_until_at_least(next_tick).await;
+        println!("{name} tick {i} at {:?}", next_tick.elapsed());
+        // Schedule the next tick from the previous deadline, not from "now", so a late tick
+        // doesn't push every later one back by the same amount.
+        next_tick += period;
+    }
+}
+
+fn main() {
+    let mut futures = Vec::new();
+    futures.push(ticker("fast", Duration::from_millis(100), 5));
+    futures.push(ticker("slow", Duration::from_millis(250), 2));
+    let mut joined_future = Box::pin(future::join_all(futures));
+    let waker = futures::task::noop_waker();
+    let mut context = Context::from_waker(&waker);
+    while joined_future.as_mut().poll(&mut context).is_pending() {
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        let next_wake = wake_times.keys().next().expect("sleep forever?");
+        thread::sleep(next_wake.saturating_duration_since(Instant::now()));
+        while let Some(entry) = wake_times.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
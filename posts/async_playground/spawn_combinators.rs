@@ -0,0 +1,346 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static WAKE_TIMES: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            let wakers_vec = wake_times.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+// A child future of join! goes through exactly three states: still running, resolved but not yet
+// handed to the caller, and handed off. Tracking that explicitly is what lets join! stop polling
+// a future the moment it's Ready, instead of relying on every Future impl to tolerate being
+// polled again after completion (most don't). race! doesn't need a Slot: it resolves as soon as
+// any one child is Ready, so no child is ever polled after that.
+enum Slot<T> {
+    Pending(Pin<Box<dyn Future<Output = T>>>),
+    Ready(T),
+    Taken,
+}
+
+impl<T> Slot<T> {
+    fn poll(&mut self, context: &mut Context) {
+        if let Slot::Pending(future) = self {
+            if let Poll::Ready(value) = future.as_mut().poll(context) {
+                *self = Slot::Ready(value);
+            }
+        }
+    }
+
+    fn is_ready(&self) -> bool {
+        matches!(self, Slot::Ready(_))
+    }
+
+    fn take(&mut self) -> T {
+        match std::mem::replace(self, Slot::Taken) {
+            Slot::Ready(value) => value,
+            Slot::Pending(_) | Slot::Taken => unreachable!("take() called before Ready, or twice"),
+        }
+    }
+}
+
+// join!(a, b, c) expands to one of these, one per arity. Each one is the same shape: a Slot per
+// child future, polled every time until all of them are Ready, at which point their outputs come
+// out as a flat tuple.
+struct Join2<A, B> {
+    a: Slot<A>,
+    b: Slot<B>,
+}
+
+impl<A, B> Join2<A, B> {
+    fn new(a: Pin<Box<dyn Future<Output = A>>>, b: Pin<Box<dyn Future<Output = B>>>) -> Join2<A, B> {
+        Join2 {
+            a: Slot::Pending(a),
+            b: Slot::Pending(b),
+        }
+    }
+}
+
+impl<A, B> Future for Join2<A, B> {
+    type Output = (A, B);
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<(A, B)> {
+        // Safety: we never move out of `self` except by swapping a Slot for Slot::Taken, which
+        // doesn't touch the data already pinned inside that Slot's Box.
+        let this = unsafe { self.get_unchecked_mut() };
+        this.a.poll(context);
+        this.b.poll(context);
+        if this.a.is_ready() && this.b.is_ready() {
+            Poll::Ready((this.a.take(), this.b.take()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+struct Join3<A, B, C> {
+    a: Slot<A>,
+    b: Slot<B>,
+    c: Slot<C>,
+}
+
+impl<A, B, C> Join3<A, B, C> {
+    fn new(
+        a: Pin<Box<dyn Future<Output = A>>>,
+        b: Pin<Box<dyn Future<Output = B>>>,
+        c: Pin<Box<dyn Future<Output = C>>>,
+    ) -> Join3<A, B, C> {
+        Join3 {
+            a: Slot::Pending(a),
+            b: Slot::Pending(b),
+            c: Slot::Pending(c),
+        }
+    }
+}
+
+impl<A, B, C> Future for Join3<A, B, C> {
+    type Output = (A, B, C);
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<(A, B, C)> {
+        let this = unsafe { self.get_unchecked_mut() };
+        this.a.poll(context);
+        this.b.poll(context);
+        this.c.poll(context);
+        if this.a.is_ready() && this.b.is_ready() && this.c.is_ready() {
+            Poll::Ready((this.a.take(), this.b.take(), this.c.take()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+struct Join4<A, B, C, D> {
+    a: Slot<A>,
+    b: Slot<B>,
+    c: Slot<C>,
+    d: Slot<D>,
+}
+
+impl<A, B, C, D> Join4<A, B, C, D> {
+    fn new(
+        a: Pin<Box<dyn Future<Output = A>>>,
+        b: Pin<Box<dyn Future<Output = B>>>,
+        c: Pin<Box<dyn Future<Output = C>>>,
+        d: Pin<Box<dyn Future<Output = D>>>,
+    ) -> Join4<A, B, C, D> {
+        Join4 {
+            a: Slot::Pending(a),
+            b: Slot::Pending(b),
+            c: Slot::Pending(c),
+            d: Slot::Pending(d),
+        }
+    }
+}
+
+impl<A, B, C, D> Future for Join4<A, B, C, D> {
+    type Output = (A, B, C, D);
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<(A, B, C, D)> {
+        let this = unsafe { self.get_unchecked_mut() };
+        this.a.poll(context);
+        this.b.poll(context);
+        this.c.poll(context);
+        this.d.poll(context);
+        if this.a.is_ready() && this.b.is_ready() && this.c.is_ready() && this.d.is_ready() {
+            Poll::Ready((this.a.take(), this.b.take(), this.c.take(), this.d.take()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+macro_rules! join {
+    ($a:expr, $b:expr $(,)?) => {
+        Join2::new(Box::pin($a), Box::pin($b))
+    };
+    ($a:expr, $b:expr, $c:expr $(,)?) => {
+        Join3::new(Box::pin($a), Box::pin($b), Box::pin($c))
+    };
+    ($a:expr, $b:expr, $c:expr, $d:expr $(,)?) => {
+        Join4::new(Box::pin($a), Box::pin($b), Box::pin($c), Box::pin($d))
+    };
+}
+
+// race!(a, b, c) resolves as soon as any one child does, wrapping its output in the matching
+// variant and dropping the rest (including their boxes) right along with the RaceFutN itself.
+enum Race2<A, B> {
+    First(A),
+    Second(B),
+}
+
+struct RaceFut2<A, B> {
+    a: Pin<Box<dyn Future<Output = A>>>,
+    b: Pin<Box<dyn Future<Output = B>>>,
+}
+
+impl<A, B> Future for RaceFut2<A, B> {
+    type Output = Race2<A, B>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Race2<A, B>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Poll::Ready(value) = this.a.as_mut().poll(context) {
+            return Poll::Ready(Race2::First(value));
+        }
+        if let Poll::Ready(value) = this.b.as_mut().poll(context) {
+            return Poll::Ready(Race2::Second(value));
+        }
+        Poll::Pending
+    }
+}
+
+enum Race3<A, B, C> {
+    First(A),
+    Second(B),
+    Third(C),
+}
+
+struct RaceFut3<A, B, C> {
+    a: Pin<Box<dyn Future<Output = A>>>,
+    b: Pin<Box<dyn Future<Output = B>>>,
+    c: Pin<Box<dyn Future<Output = C>>>,
+}
+
+impl<A, B, C> Future for RaceFut3<A, B, C> {
+    type Output = Race3<A, B, C>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Race3<A, B, C>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Poll::Ready(value) = this.a.as_mut().poll(context) {
+            return Poll::Ready(Race3::First(value));
+        }
+        if let Poll::Ready(value) = this.b.as_mut().poll(context) {
+            return Poll::Ready(Race3::Second(value));
+        }
+        if let Poll::Ready(value) = this.c.as_mut().poll(context) {
+            return Poll::Ready(Race3::Third(value));
+        }
+        Poll::Pending
+    }
+}
+
+enum Race4<A, B, C, D> {
+    First(A),
+    Second(B),
+    Third(C),
+    Fourth(D),
+}
+
+struct RaceFut4<A, B, C, D> {
+    a: Pin<Box<dyn Future<Output = A>>>,
+    b: Pin<Box<dyn Future<Output = B>>>,
+    c: Pin<Box<dyn Future<Output = C>>>,
+    d: Pin<Box<dyn Future<Output = D>>>,
+}
+
+impl<A, B, C, D> Future for RaceFut4<A, B, C, D> {
+    type Output = Race4<A, B, C, D>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Race4<A, B, C, D>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Poll::Ready(value) = this.a.as_mut().poll(context) {
+            return Poll::Ready(Race4::First(value));
+        }
+        if let Poll::Ready(value) = this.b.as_mut().poll(context) {
+            return Poll::Ready(Race4::Second(value));
+        }
+        if let Poll::Ready(value) = this.c.as_mut().poll(context) {
+            return Poll::Ready(Race4::Third(value));
+        }
+        if let Poll::Ready(value) = this.d.as_mut().poll(context) {
+            return Poll::Ready(Race4::Fourth(value));
+        }
+        Poll::Pending
+    }
+}
+
+macro_rules! race {
+    ($a:expr, $b:expr $(,)?) => {
+        RaceFut2 { a: Box::pin($a), b: Box::pin($b) }
+    };
+    ($a:expr, $b:expr, $c:expr $(,)?) => {
+        RaceFut3 { a: Box::pin($a), b: Box::pin($b), c: Box::pin($c) }
+    };
+    ($a:expr, $b:expr, $c:expr, $d:expr $(,)?) => {
+        RaceFut4 { a: Box::pin($a), b: Box::pin($b), c: Box::pin($c), d: Box::pin($d) }
+    };
+}
+
+struct Elapsed;
+
+fn timeout<F>(duration: Duration, future: F) -> impl Future<Output = Result<F::Output, Elapsed>>
+where
+    F: Future + 'static,
+{
+    async move {
+        match race!(future, sleep(duration)).await {
+            Race2::First(value) => Ok(value),
+            Race2::Second(()) => Err(Elapsed),
+        }
+    }
+}
+
+async fn slow_double(n: u64, delay_ms: u64) -> u64 {
+    sleep(Duration::from_millis(delay_ms)).await;
+    n * 2
+}
+
+async fn async_main() {
+    let (a, b, c) = join!(slow_double(1, 300), slow_double(2, 100), slow_double(3, 200)).await;
+    println!("join!: {a} {b} {c}");
+
+    match race!(slow_double(4, 300), slow_double(5, 100)).await {
+        Race2::First(value) => println!("race!: first branch won with {value}"),
+        Race2::Second(value) => println!("race!: second branch won with {value}"),
+    }
+
+    match timeout(Duration::from_millis(50), slow_double(6, 200)).await {
+        Ok(value) => println!("timeout: completed with {value}"),
+        Err(Elapsed) => println!("timeout: elapsed before the future finished"),
+    }
+}
+
+fn main() {
+    let waker = futures::task::noop_waker();
+    let mut context = Context::from_waker(&waker);
+    let mut main_task = Box::pin(async_main());
+    loop {
+        if main_task.as_mut().poll(&mut context).is_ready() {
+            return;
+        }
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        let next_wake = wake_times.keys().next().expect("sleep forever?");
+        thread::sleep(next_wake.saturating_duration_since(Instant::now()));
+        while let Some(entry) = wake_times.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// This chunk's spawn() everywhere else requires F: Send, so a future that captures an Rc or a
+// RefCell -- or this thread-local WAKE_TIMES -- can never be spawned onto it. A LocalSet groups
+// !Send tasks on the one thread that's driving it instead.
+thread_local! {
+    static WAKE_TIMES: RefCell<BTreeMap<Instant, Vec<Waker>>> = RefCell::new(BTreeMap::new());
+}
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            WAKE_TIMES.with_borrow_mut(|wake_times| {
+                let wakers_vec = wake_times.entry(self.wake_time).or_default();
+                wakers_vec.push(context.waker().clone());
+                Poll::Pending
+            })
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+type LocalFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+// Waking a local task only ever needs to push its id onto a queue and nudge whichever Waker the
+// enclosing run_until call last handed to us. Neither of those operations touches the !Send
+// future itself, so this type can be Send + Sync even though the tasks it wakes are not --
+// that's what lets it satisfy Wake's `Arc<Self>: Send + Sync` bound.
+struct LocalWaker {
+    id: usize,
+    ready_queue: Arc<Mutex<VecDeque<usize>>>,
+    outer_waker: Arc<Mutex<Option<Waker>>>,
+}
+
+impl Wake for LocalWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.ready_queue.lock().unwrap().push_back(self.id);
+        if let Some(waker) = self.outer_waker.lock().unwrap().clone() {
+            waker.wake();
+        }
+    }
+}
+
+struct LocalSetInner {
+    tasks: RefCell<BTreeMap<usize, LocalFuture>>,
+    next_id: RefCell<usize>,
+    ready_queue: Arc<Mutex<VecDeque<usize>>>,
+    outer_waker: Arc<Mutex<Option<Waker>>>,
+}
+
+// Groups !Send tasks pinned to whichever thread calls run_until. Unlike the spawn()/JoinHandle
+// pairs elsewhere in this chunk, spawn_local tasks are detached: there's no handle to await their
+// output, only a guarantee that run_until won't return until every one of them has finished.
+struct LocalSet {
+    inner: Rc<LocalSetInner>,
+}
+
+thread_local! {
+    // A stack rather than a single slot, so a nested run_until call (unusual, but not forbidden)
+    // can still find its own LocalSet and correctly restore the outer one on the way out.
+    static CURRENT: RefCell<Vec<Rc<LocalSetInner>>> = RefCell::new(Vec::new());
+}
+
+fn spawn_local<F: Future<Output = ()> + 'static>(future: F) {
+    CURRENT.with_borrow(|stack| {
+        let inner = stack.last().expect("spawn_local called outside of LocalSet::run_until");
+        let id = {
+            let mut next_id = inner.next_id.borrow_mut();
+            let id = *next_id;
+            *next_id += 1;
+            id
+        };
+        inner.tasks.borrow_mut().insert(id, Box::pin(future));
+        inner.ready_queue.lock().unwrap().push_back(id);
+    });
+}
+
+impl LocalSet {
+    fn new() -> LocalSet {
+        LocalSet {
+            inner: Rc::new(LocalSetInner {
+                tasks: RefCell::new(BTreeMap::new()),
+                next_id: RefCell::new(0),
+                ready_queue: Arc::new(Mutex::new(VecDeque::new())),
+                outer_waker: Arc::new(Mutex::new(None)),
+            }),
+        }
+    }
+
+    // Makes this the current LocalSet for the duration of polling `future` (so spawn_local calls
+    // inside it land here), and drives both `future` and whatever it spawns to completion.
+    async fn run_until<F: Future>(&self, future: F) -> F::Output {
+        CURRENT.with_borrow_mut(|stack| stack.push(Rc::clone(&self.inner)));
+        let _guard = PopOnDrop;
+
+        let mut future = std::pin::pin!(future);
+        let mut output = None;
+        std::future::poll_fn(|context| {
+            *self.inner.outer_waker.lock().unwrap() = Some(context.waker().clone());
+            if output.is_none() {
+                if let Poll::Ready(value) = future.as_mut().poll(context) {
+                    output = Some(value);
+                }
+            }
+            self.poll_ready_tasks();
+            if output.is_some() && self.inner.tasks.borrow().is_empty() {
+                return Poll::Ready(output.take().unwrap());
+            }
+            Poll::Pending
+        })
+        .await
+    }
+
+    fn poll_ready_tasks(&self) {
+        loop {
+            let Some(id) = self.inner.ready_queue.lock().unwrap().pop_front() else {
+                break;
+            };
+            let Some(mut future) = self.inner.tasks.borrow_mut().remove(&id) else {
+                // Woken more than once before its next poll; the second id is a no-op.
+                continue;
+            };
+            let waker = Waker::from(Arc::new(LocalWaker {
+                id,
+                ready_queue: Arc::clone(&self.inner.ready_queue),
+                outer_waker: Arc::clone(&self.inner.outer_waker),
+            }));
+            let mut context = Context::from_waker(&waker);
+            if future.as_mut().poll(&mut context).is_pending() {
+                self.inner.tasks.borrow_mut().insert(id, future);
+            }
+        }
+    }
+}
+
+struct PopOnDrop;
+
+impl Drop for PopOnDrop {
+    fn drop(&mut self) {
+        CURRENT.with_borrow_mut(|stack| {
+            stack.pop();
+        });
+    }
+}
+
+async fn background_ticker(label: &'static str, count: u64) {
+    for i in 1..=count {
+        sleep(Duration::from_millis(200)).await;
+        println!("{label}: tick {i}/{count}");
+    }
+}
+
+async fn async_main() {
+    // Rc isn't Send, so this could never be handed to the Send-bound spawn() elsewhere in this
+    // chunk; spawn_local accepts it because everything stays on this one thread.
+    let shared_counter = Rc::new(RefCell::new(0));
+
+    let counter = Rc::clone(&shared_counter);
+    spawn_local(async move {
+        for _ in 0..3 {
+            sleep(Duration::from_millis(150)).await;
+            *counter.borrow_mut() += 1;
+        }
+    });
+    spawn_local(background_ticker("background", 4));
+
+    sleep(Duration::from_millis(900)).await;
+    println!("shared_counter: {}", shared_counter.borrow());
+}
+
+fn main() {
+    let local_set = LocalSet::new();
+    let mut main_task = Box::pin(local_set.run_until(async_main()));
+    let waker = futures::task::noop_waker();
+    let mut context = Context::from_waker(&waker);
+    while main_task.as_mut().poll(&mut context).is_pending() {
+        WAKE_TIMES.with_borrow_mut(|wake_times| {
+            let next_wake = wake_times.keys().next().expect("sleep forever?");
+            thread::sleep(next_wake.saturating_duration_since(Instant::now()));
+            while let Some(entry) = wake_times.first_entry() {
+                if *entry.key() <= Instant::now() {
+                    entry.remove().into_iter().for_each(Waker::wake);
+                } else {
+                    break;
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static WAKE_TIMES: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            let wakers_vec = wake_times.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+static NEW_TASKS: Mutex<Vec<DynFuture>> = Mutex::new(Vec::new());
+
+// Previously spawn<F: Future<Output = ()>> could only take (), so a spawned task's result just
+// vanished. This is a small oneshot channel -- one value, sent once -- shared between the wrapped
+// task and the JoinHandle the caller gets back, so a task's output can cross that boundary.
+struct OneshotState<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<OneshotState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(value) = state.value.take() {
+            Poll::Ready(value)
+        } else {
+            state.waker = Some(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let state = Arc::new(Mutex::new(OneshotState {
+        value: None,
+        waker: None,
+    }));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&state),
+    };
+    // The real future type goes in here, but NEW_TASKS only knows about Output = (): the task
+    // stores its result in `state` itself instead of returning it from poll. If the JoinHandle
+    // gets dropped before this runs, nobody's left to read `state.value`, and it's just dropped
+    // along with the Arc -- the task still has to run to completion either way.
+    let wrapped = Box::pin(async move {
+        let value = future.await;
+        let mut state = state.lock().unwrap();
+        state.value = Some(value);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    });
+    NEW_TASKS.lock().unwrap().push(wrapped);
+    join_handle
+}
+
+async fn square(n: u64) -> u64 {
+    sleep(Duration::from_millis(100 * (11 - n))).await;
+    n * n
+}
+
+async fn async_main() {
+    let mut task_handles = Vec::new();
+    for n in 1..=10 {
+        task_handles.push(spawn(square(n)));
+    }
+    for handle in task_handles {
+        println!("{}", handle.await);
+    }
+}
+
+fn main() {
+    let waker = futures::task::noop_waker();
+    let mut context = Context::from_waker(&waker);
+    let mut main_task = Box::pin(async_main());
+    let mut other_tasks: Vec<DynFuture> = Vec::new();
+    loop {
+        // Poll the main task and exit immediately if it's done.
+        if main_task.as_mut().poll(&mut context).is_ready() {
+            return;
+        }
+        // Poll other tasks and remove any that are Ready.
+        let is_pending = |task: &mut DynFuture| task.as_mut().poll(&mut context).is_pending();
+        other_tasks.retain_mut(is_pending);
+        // Some tasks might have spawned new tasks. Pop from NEW_TASKS until it's empty. Note that
+        // we can't use while-let here, because that would keep NEW_TASKS locked in the loop body.
+        // See https://fasterthanli.me/articles/a-rust-match-made-in-hell.
+        loop {
+            let Some(mut task) = NEW_TASKS.lock().unwrap().pop() else {
+                break;
+            };
+            // Poll each new task now, instead of waiting for the next iteration of the main loop,
+            // to let them register wakeups. Drop the ones that return Ready. This poll can also
+            // spawn more tasks, so it's important that NEW_TASKS isn't locked here.
+            if task.as_mut().poll(&mut context).is_pending() {
+                other_tasks.push(task);
+            }
+        }
+        // Sleep until the next Waker is scheduled and then invoke Wakers that are ready.
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        let Some(next_wake) = wake_times.keys().next().copied() else {
+            // Nothing is asleep. The main task was polled before the other tasks, so go back to
+            // the top and poll it again: the tasks it's waiting on may have just finished.
+            continue;
+        };
+        thread::sleep(next_wake.saturating_duration_since(Instant::now()));
+        while let Some(entry) = wake_times.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
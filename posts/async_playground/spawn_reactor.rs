@@ -0,0 +1,456 @@
+use slab::Slab;
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static WAKE_TIMES: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            let wakers_vec = wake_times.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+// Same per-task waker / ready queue design as spawn_ready_queue.rs: waking a task just drops its
+// id onto READY_QUEUE instead of touching any other task, and READY_CONDVAR lets the executor
+// thread block instead of spinning when the queue is empty.
+struct Task {
+    id: usize,
+    future: DynFuture,
+}
+
+static TASKS: Mutex<BTreeMap<usize, Task>> = Mutex::new(BTreeMap::new());
+static READY_QUEUE: Mutex<VecDeque<usize>> = Mutex::new(VecDeque::new());
+static READY_CONDVAR: Condvar = Condvar::new();
+static NEXT_ID: Mutex<usize> = Mutex::new(0);
+
+struct TaskWaker {
+    id: usize,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        READY_QUEUE.lock().unwrap().push_back(self.id);
+        READY_CONDVAR.notify_one();
+    }
+}
+
+struct OneshotState<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<OneshotState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(value) = state.value.take() {
+            Poll::Ready(value)
+        } else {
+            state.waker = Some(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let state = Arc::new(Mutex::new(OneshotState {
+        value: None,
+        waker: None,
+    }));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&state),
+    };
+    let wrapped: DynFuture = Box::pin(async move {
+        let value = future.await;
+        let mut state = state.lock().unwrap();
+        state.value = Some(value);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    });
+    let id = {
+        let mut next_id = NEXT_ID.lock().unwrap();
+        let id = *next_id;
+        *next_id += 1;
+        id
+    };
+    TASKS.lock().unwrap().insert(id, Task { id, future: wrapped });
+    READY_QUEUE.lock().unwrap().push_back(id);
+    READY_CONDVAR.notify_one();
+    join_handle
+}
+
+// One registration in the reactor's slab: the fd's read/write Wakers, stashed here instead of
+// handed straight to epoll, since epoll only knows fds and readiness bits, not Wakers.
+struct Entry {
+    readable: Mutex<Option<Waker>>,
+    writable: Mutex<Option<Waker>>,
+}
+
+// Modeled on smol's reactor, except that it owns a dedicated background thread instead of being
+// polled from inside the executor's main loop: the executor thread now just blocks on
+// READY_CONDVAR, and this thread is the only one that ever calls epoll_wait. Folding WAKE_TIMES
+// into that same wait means sleeps and I/O share one blocking point instead of two.
+struct Reactor {
+    epoll_fd: RawFd,
+    entries: Mutex<Slab<Arc<Entry>>>,
+}
+
+impl Reactor {
+    fn new() -> Reactor {
+        let epoll_fd = unsafe { libc::epoll_create1(0) };
+        if epoll_fd == -1 {
+            panic!("epoll_create1 failed: {}", io::Error::last_os_error());
+        }
+        Reactor {
+            epoll_fd,
+            entries: Mutex::new(Slab::new()),
+        }
+    }
+
+    fn register(&self, fd: RawFd) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let key = entries.insert(Arc::new(Entry {
+            readable: Mutex::new(None),
+            writable: Mutex::new(None),
+        }));
+        let mut event = libc::epoll_event {
+            events: (libc::EPOLLIN | libc::EPOLLOUT | libc::EPOLLET) as u32,
+            u64: key as u64,
+        };
+        let result = unsafe { libc::epoll_ctl(self.epoll_fd, libc::EPOLL_CTL_ADD, fd, &mut event) };
+        if result == -1 {
+            panic!("epoll_ctl(ADD) failed: {}", io::Error::last_os_error());
+        }
+        key
+    }
+
+    fn deregister(&self, key: usize, fd: RawFd) {
+        let result = unsafe {
+            libc::epoll_ctl(self.epoll_fd, libc::EPOLL_CTL_DEL, fd, std::ptr::null_mut())
+        };
+        if result == -1 {
+            panic!("epoll_ctl(DEL) failed: {}", io::Error::last_os_error());
+        }
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    fn entry(&self, key: usize) -> Arc<Entry> {
+        Arc::clone(&self.entries.lock().unwrap()[key])
+    }
+
+    // One iteration of the background thread's loop: block in epoll_wait, using the earliest
+    // WAKE_TIMES entry as the timeout, then wake whatever came due, I/O or timer alike.
+    fn wait_and_wake(&self) {
+        let wake_times = WAKE_TIMES.lock().unwrap();
+        let timeout_ms = if let Some(time) = wake_times.keys().next() {
+            time.saturating_duration_since(Instant::now()).as_millis() as libc::c_int
+        } else {
+            -1 // infinite timeout
+        };
+        // Don't hold WAKE_TIMES locked across the blocking syscall, or no task could register a
+        // new sleep() while we're waiting.
+        drop(wake_times);
+
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; 1024];
+        let num_events = unsafe {
+            libc::epoll_wait(self.epoll_fd, events.as_mut_ptr(), events.len() as libc::c_int, timeout_ms)
+        };
+        if num_events == -1 {
+            panic!("epoll_wait failed: {}", io::Error::last_os_error());
+        }
+
+        let entries = self.entries.lock().unwrap();
+        for event in &events[..num_events as usize] {
+            let Some(entry) = entries.get(event.u64 as usize) else {
+                continue;
+            };
+            if event.events & (libc::EPOLLIN | libc::EPOLLHUP | libc::EPOLLERR) as u32 != 0 {
+                if let Some(waker) = entry.readable.lock().unwrap().take() {
+                    waker.wake();
+                }
+            }
+            if event.events & (libc::EPOLLOUT | libc::EPOLLHUP | libc::EPOLLERR) as u32 != 0 {
+                if let Some(waker) = entry.writable.lock().unwrap().take() {
+                    waker.wake();
+                }
+            }
+        }
+        drop(entries);
+
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        while let Some(entry) = wake_times.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+fn reactor() -> &'static Reactor {
+    static REACTOR: OnceLock<Reactor> = OnceLock::new();
+    REACTOR.get_or_init(Reactor::new)
+}
+
+// Spawned once from main(); runs for the lifetime of the process, since epoll_wait(-1) with no
+// registered sources just blocks forever rather than exiting.
+fn run_reactor_thread() {
+    loop {
+        reactor().wait_and_wake();
+    }
+}
+
+// Wraps any AsRawFd type, registering it with the reactor once instead of each caller open-coding
+// its own poll_fn + register/deregister pair.
+struct Async<T: AsRawFd> {
+    inner: T,
+    key: usize,
+}
+
+impl<T: AsRawFd> Async<T> {
+    fn new(io: T) -> io::Result<Async<T>> {
+        // Callers are responsible for having already called set_nonblocking; AsRawFd alone
+        // doesn't give us a portable way to do that ourselves.
+        let key = reactor().register(io.as_raw_fd());
+        Ok(Async { inner: io, key })
+    }
+
+    // Retry `op` against the inner value until it stops returning WouldBlock, awaiting
+    // read-readiness in between attempts.
+    async fn read_with<R>(&self, mut op: impl FnMut(&T) -> io::Result<R>) -> io::Result<R> {
+        loop {
+            match op(&self.inner) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => self.readable().await,
+                result => return result,
+            }
+        }
+    }
+
+    // Same as read_with, but waits for write-readiness.
+    async fn write_with<R>(&self, mut op: impl FnMut(&T) -> io::Result<R>) -> io::Result<R> {
+        loop {
+            match op(&self.inner) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => self.writable().await,
+                result => return result,
+            }
+        }
+    }
+
+    // Park until the reactor wakes this entry's readable slot. A bare poll_fn can't do this by
+    // itself, since by the time it's first polled no readiness event has happened yet: register
+    // the waker on the first poll and return Pending, then report Ready the next time we're
+    // polled, which only happens once the reactor wakes us.
+    async fn readable(&self) {
+        let mut registered = false;
+        std::future::poll_fn(|context| {
+            if mem::replace(&mut registered, true) {
+                Poll::Ready(())
+            } else {
+                *reactor().entry(self.key).readable.lock().unwrap() = Some(context.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    async fn writable(&self) {
+        let mut registered = false;
+        std::future::poll_fn(|context| {
+            if mem::replace(&mut registered, true) {
+                Poll::Ready(())
+            } else {
+                *reactor().entry(self.key).writable.lock().unwrap() = Some(context.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+impl<T: AsRawFd> Drop for Async<T> {
+    fn drop(&mut self) {
+        reactor().deregister(self.key, self.inner.as_raw_fd());
+    }
+}
+
+impl Async<TcpListener> {
+    fn bind(address: &str) -> io::Result<Async<TcpListener>> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Async::new(listener)
+    }
+
+    async fn accept(&self) -> io::Result<(Async<TcpStream>, SocketAddr)> {
+        let (stream, addr) = self.read_with(|listener| listener.accept()).await?;
+        stream.set_nonblocking(true)?;
+        Ok((Async::new(stream)?, addr))
+    }
+}
+
+impl Async<TcpStream> {
+    fn connect(address: &str) -> io::Result<Async<TcpStream>> {
+        // XXX: Assume that connect() returns quickly.
+        let stream = TcpStream::connect(address)?;
+        stream.set_nonblocking(true)?;
+        Async::new(stream)
+    }
+
+    async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match (&self.inner).read(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => self.readable().await,
+                result => return result,
+            }
+        }
+    }
+
+    async fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            match (&self.inner).write(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => self.writable().await,
+                result => return result,
+            }
+        }
+    }
+}
+
+async fn write_all(socket: &Async<TcpStream>, mut buf: &[u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        let n = socket.write(buf).await?;
+        if n == 0 {
+            return Err(io::Error::from(io::ErrorKind::WriteZero));
+        }
+        buf = &buf[n..];
+    }
+    Ok(())
+}
+
+async fn print_all(socket: &Async<TcpStream>) -> io::Result<()> {
+    let mut buf = [0; 1024];
+    loop {
+        let n = socket.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(()); // EOF
+        }
+        // Assume that printing doesn't block.
+        io::stdout().write_all(&buf[..n])?;
+    }
+}
+
+async fn one_response(socket: Async<TcpStream>, n: u64) -> io::Result<()> {
+    // Using format! instead of write! avoids breaking up lines across multiple writes. This is
+    // easier than doing line buffering on the client side.
+    let start_msg = format!("start {n}\n");
+    write_all(&socket, start_msg.as_bytes()).await?;
+    sleep(Duration::from_secs(1)).await;
+    let end_msg = format!("end {n}\n");
+    write_all(&socket, end_msg.as_bytes()).await?;
+    Ok(())
+}
+
+async fn server_main(listener: Async<TcpListener>) -> io::Result<()> {
+    let mut n = 1;
+    loop {
+        let (socket, _) = listener.accept().await?;
+        spawn(async move { one_response(socket, n).await.unwrap() });
+        n += 1;
+    }
+}
+
+async fn client_main() -> io::Result<()> {
+    let socket = Async::<TcpStream>::connect("localhost:8000")?;
+    print_all(&socket).await?;
+    Ok(())
+}
+
+async fn async_main() -> io::Result<()> {
+    // Avoid a race between bind and connect by binding first.
+    let listener = Async::<TcpListener>::bind("0.0.0.0:8000")?;
+    spawn(async { server_main(listener).await.unwrap() });
+    let mut task_handles = Vec::new();
+    for _ in 1..=10 {
+        task_handles.push(spawn(client_main()));
+    }
+    for handle in task_handles {
+        handle.await?;
+    }
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    thread::spawn(run_reactor_thread);
+
+    // A one-slot channel just to get async_main's result back out to the executor loop below.
+    let (done_sender, done_receiver) = crossbeam_channel::bounded(1);
+    spawn(async move {
+        let result = async_main().await;
+        done_sender.send(result).expect("executor loop is waiting");
+    });
+
+    loop {
+        if let Ok(result) = done_receiver.try_recv() {
+            return result;
+        }
+        let mut queue = READY_QUEUE.lock().unwrap();
+        while queue.is_empty() {
+            queue = READY_CONDVAR.wait(queue).unwrap();
+        }
+        let id = queue.pop_front().unwrap();
+        drop(queue);
+        let Some(mut task) = TASKS.lock().unwrap().remove(&id) else {
+            // The id was already polled to completion and removed; a task can be woken more than
+            // once before it's next polled, so a stale id in the queue is expected.
+            continue;
+        };
+        let waker = Waker::from(Arc::new(TaskWaker { id: task.id }));
+        let mut context = Context::from_waker(&waker);
+        if task.future.as_mut().poll(&mut context).is_pending() {
+            TASKS.lock().unwrap().insert(id, task);
+        }
+    }
+}
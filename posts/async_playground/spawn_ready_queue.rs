@@ -0,0 +1,173 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static WAKE_TIMES: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            let wakers_vec = wake_times.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+// spawn_oneshot.rs's main loop polls every live task on every wakeup, even though only one of them
+// might actually be ready to make progress -- fine for ten tasks, ruinous for ten thousand. Here
+// each task gets its own id and its own Waker, and waking a task just drops its id onto a shared
+// queue instead of touching any other task's state.
+struct Task {
+    id: usize,
+    future: DynFuture,
+}
+
+static TASKS: Mutex<BTreeMap<usize, Task>> = Mutex::new(BTreeMap::new());
+static READY_QUEUE: Mutex<VecDeque<usize>> = Mutex::new(VecDeque::new());
+static NEXT_ID: Mutex<usize> = Mutex::new(0);
+
+struct TaskWaker {
+    id: usize,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        READY_QUEUE.lock().unwrap().push_back(self.id);
+    }
+}
+
+struct OneshotState<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<OneshotState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(value) = state.value.take() {
+            Poll::Ready(value)
+        } else {
+            state.waker = Some(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let state = Arc::new(Mutex::new(OneshotState {
+        value: None,
+        waker: None,
+    }));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&state),
+    };
+    let wrapped: DynFuture = Box::pin(async move {
+        let value = future.await;
+        let mut state = state.lock().unwrap();
+        state.value = Some(value);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    });
+    let id = {
+        let mut next_id = NEXT_ID.lock().unwrap();
+        let id = *next_id;
+        *next_id += 1;
+        id
+    };
+    TASKS.lock().unwrap().insert(id, Task { id, future: wrapped });
+    // A newly spawned task hasn't been polled yet, so it needs to start in the queue to get its
+    // first poll at all; nothing else would ever wake it otherwise.
+    READY_QUEUE.lock().unwrap().push_back(id);
+    join_handle
+}
+
+async fn square(n: u64) -> u64 {
+    sleep(Duration::from_millis(100 * (11 - n))).await;
+    n * n
+}
+
+async fn async_main() {
+    let mut task_handles = Vec::new();
+    for n in 1..=10 {
+        task_handles.push(spawn(square(n)));
+    }
+    for handle in task_handles {
+        println!("{}", handle.await);
+    }
+}
+
+fn main() {
+    let waker = futures::task::noop_waker();
+    let mut context = Context::from_waker(&waker);
+    let mut main_task = Box::pin(async_main());
+    loop {
+        if main_task.as_mut().poll(&mut context).is_ready() {
+            return;
+        }
+        loop {
+            let Some(id) = READY_QUEUE.lock().unwrap().pop_front() else {
+                break;
+            };
+            let Some(mut task) = TASKS.lock().unwrap().remove(&id) else {
+                // The id was already polled to completion and removed; a task can be woken more
+                // than once before it's next polled, so a stale id in the queue is expected.
+                continue;
+            };
+            let waker = Waker::from(Arc::new(TaskWaker { id: task.id }));
+            let mut task_context = Context::from_waker(&waker);
+            if task.future.as_mut().poll(&mut task_context).is_pending() {
+                TASKS.lock().unwrap().insert(id, task);
+            }
+        }
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        let Some(next_wake) = wake_times.keys().next().copied() else {
+            // Nothing is asleep. The main task isn't in the ready queue, so go back to the top
+            // and poll it: the tasks it's waiting on may have just finished.
+            continue;
+        };
+        thread::sleep(next_wake.saturating_duration_since(Instant::now()));
+        while let Some(entry) = wake_times.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+use crossbeam_utils::sync::{Parker, Unparker};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+// Every other runtime in this chunk polls all of its tasks from a single thread, so one
+// CPU-bound task -- or a task that blocks the thread outright with std::thread::sleep instead of
+// this file's async sleep() -- stalls every other task behind it. This version spreads tasks
+// across num_cpus worker threads instead, the same design smol and juliex use.
+static WAKE_TIMES: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            let wakers_vec = wake_times.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+// A Runnable is just a Task behind an Arc: waking it pushes the Arc back onto the injector
+// instead of touching any other task, and any worker (not just the one that polled it last) can
+// pick it up next.
+static INJECTOR: OnceLock<Injector<Arc<Task>>> = OnceLock::new();
+static STEALERS: OnceLock<Vec<Stealer<Arc<Task>>>> = OnceLock::new();
+static UNPARKERS: OnceLock<Vec<Unparker>> = OnceLock::new();
+
+fn injector() -> &'static Injector<Arc<Task>> {
+    INJECTOR.get_or_init(Injector::new)
+}
+
+fn wake_workers() {
+    if let Some(unparkers) = UNPARKERS.get() {
+        for unparker in unparkers {
+            unparker.unpark();
+        }
+    }
+}
+
+struct Task {
+    future: Mutex<Option<DynFuture>>,
+    // Guards against double-enqueueing a task that's woken more than once before it's next
+    // polled. Cleared right before polling, so a wakeup that arrives *during* that poll still
+    // results in exactly one re-enqueue.
+    scheduled: AtomicBool,
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.scheduled.swap(true, Ordering::AcqRel) {
+            injector().push(Arc::clone(self));
+            wake_workers();
+        }
+    }
+}
+
+fn poll_task(task: &Arc<Task>, context: &mut Context) {
+    task.scheduled.store(false, Ordering::Release);
+    let mut future_slot = task.future.lock().unwrap();
+    // The future is already gone if the task was woken during the poll that finished it.
+    let Some(future) = future_slot.as_mut() else {
+        return;
+    };
+    if future.as_mut().poll(context).is_ready() {
+        *future_slot = None;
+    }
+}
+
+struct OneshotState<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<OneshotState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(value) = state.value.take() {
+            Poll::Ready(value)
+        } else {
+            state.waker = Some(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let state = Arc::new(Mutex::new(OneshotState {
+        value: None,
+        waker: None,
+    }));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&state),
+    };
+    let wrapped: DynFuture = Box::pin(async move {
+        let value = future.await;
+        let mut state = state.lock().unwrap();
+        state.value = Some(value);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    });
+    let task = Arc::new(Task {
+        future: Mutex::new(Some(wrapped)),
+        scheduled: AtomicBool::new(true),
+    });
+    injector().push(task);
+    wake_workers();
+    join_handle
+}
+
+// Each worker pops from its own local deque first, then the shared injector (taking a whole
+// batch at once to amortize the lock), and only then tries to steal from a sibling.
+fn find_task(local: &Worker<Arc<Task>>) -> Option<Arc<Task>> {
+    local.pop().or_else(|| {
+        std::iter::repeat_with(|| {
+            injector()
+                .steal_batch_and_pop(local)
+                .or_else(|| STEALERS.get().unwrap().iter().map(Stealer::steal).collect())
+        })
+        .find(|s| !s.is_retry())
+        .and_then(Steal::success)
+    })
+}
+
+fn worker_loop(local: Worker<Arc<Task>>, parker: Parker) {
+    loop {
+        match find_task(&local) {
+            Some(task) => {
+                let waker = Waker::from(Arc::clone(&task));
+                let mut context = Context::from_waker(&waker);
+                poll_task(&task, &mut context);
+            }
+            None => parker.park_timeout(Duration::from_millis(10)),
+        }
+    }
+}
+
+// A dedicated thread to fire due timers, since no single worker thread owns WAKE_TIMES the way
+// the single-threaded executors in this chunk do.
+fn timer_loop() {
+    loop {
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        let timeout = if let Some(time) = wake_times.keys().next() {
+            // Cap the wait so a later, shorter-lived sleep() that gets registered while we're
+            // waiting still gets noticed promptly, instead of waiting out the first one's timer.
+            time.saturating_duration_since(Instant::now()).min(Duration::from_millis(100))
+        } else {
+            Duration::from_millis(100)
+        };
+        drop(wake_times);
+        thread::sleep(timeout);
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        while let Some(entry) = wake_times.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+// Starts the worker pool and timer thread exactly once, the first time block_on is called. The
+// threads are never joined -- like spawn_reactor.rs's background reactor thread, they run for
+// the lifetime of the process and exit along with it.
+fn start_pool() {
+    static STARTED: OnceLock<()> = OnceLock::new();
+    STARTED.get_or_init(|| {
+        let num_workers = num_cpus::get();
+        let workers: Vec<Worker<Arc<Task>>> = (0..num_workers).map(|_| Worker::new_fifo()).collect();
+        STEALERS
+            .set(workers.iter().map(Worker::stealer).collect())
+            .unwrap();
+        let parkers: Vec<Parker> = (0..num_workers).map(|_| Parker::new()).collect();
+        UNPARKERS
+            .set(parkers.iter().map(Parker::unparker).cloned().collect())
+            .unwrap();
+        for (local, parker) in workers.into_iter().zip(parkers) {
+            thread::spawn(move || worker_loop(local, parker));
+        }
+        thread::spawn(timer_loop);
+    });
+}
+
+// A Waker that just unparks the thread block_on is running on, the same trick
+// futures::executor::block_on uses: the root future doesn't need its own queue or worker, since
+// there's only ever one of it and it's driven inline.
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+// Drives `future` to completion on the calling thread, while the worker pool (started here, on
+// first use) handles anything spawned along the way.
+fn block_on<F: Future>(future: F) -> F::Output {
+    start_pool();
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut context = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(value) => return value,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+// Busy-waits for a bit instead of calling sleep().await: on a single-threaded executor this
+// would stall every other task, but here it only ties up the worker thread running it.
+async fn job(n: u64) -> u64 {
+    let busy_start = Instant::now();
+    while Instant::now() < busy_start + Duration::from_millis(50) {}
+    sleep(Duration::from_millis(100)).await;
+    n * n
+}
+
+async fn async_main() {
+    println!("Number of CPUs: {}\n", num_cpus::get());
+    let mut task_handles = Vec::new();
+    for n in 1..=20 {
+        task_handles.push(spawn(job(n)));
+    }
+    for handle in task_handles {
+        println!("{}", handle.await);
+    }
+}
+
+fn main() {
+    block_on(async_main());
+}
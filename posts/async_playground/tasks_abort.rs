@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static WAKERS: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wakers_tree = WAKERS.lock().unwrap();
+            let wakers_vec = wakers_tree.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+// Each new task carries its cancellation flag and an on_abort callback alongside its boxed
+// future, so the main loop can check the flag in retain_mut (and when first popping from
+// NEW_TASKS) without ever polling a future that's already been aborted, and can still notify a
+// joiner even though AbortHandle itself doesn't know the task's JoinState<T>.
+type OnAbort = Arc<dyn Fn() + Send + Sync>;
+static NEW_TASKS: Mutex<Vec<(DynFuture, Arc<AtomicBool>, OnAbort)>> = Mutex::new(Vec::new());
+
+#[derive(Debug)]
+struct Aborted;
+
+impl fmt::Display for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "task was aborted")
+    }
+}
+
+impl std::error::Error for Aborted {}
+
+enum JoinState<T> {
+    Unawaited,
+    Awaited(Waker),
+    Ready(T),
+    Aborted,
+    Done,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<T> JoinHandle<T> {
+    // A cloneable, non-owning handle that can cancel the task from elsewhere, e.g. after moving
+    // the JoinHandle itself into a select! branch.
+    fn abort_handle(&self) -> AbortHandle {
+        AbortHandle {
+            cancelled: Arc::clone(&self.cancelled),
+        }
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, Aborted>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Result<T, Aborted>> {
+        let mut guard = self.state.lock().unwrap();
+        // Use JoinState::Done as a placeholder, to take ownership of T.
+        match mem::replace(&mut *guard, JoinState::Done) {
+            JoinState::Ready(value) => Poll::Ready(Ok(value)),
+            JoinState::Aborted => Poll::Ready(Err(Aborted)),
+            JoinState::Unawaited | JoinState::Awaited(_) => {
+                *guard = JoinState::Awaited(context.waker().clone());
+                Poll::Pending
+            }
+            JoinState::Done => unreachable!("polled again after Ready or Aborted"),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct AbortHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl AbortHandle {
+    // Setting the flag is all abort() does directly. The actual drop of the boxed future (and
+    // everything it owns: locals, channel senders, guards) happens later, in main's loop, the next
+    // time that task would otherwise have been polled. We don't wake anything here, because the
+    // task itself isn't necessarily being awaited; see JoinHandle::poll for how a joiner finds out.
+    fn abort(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&join_state),
+        cancelled: Arc::clone(&cancelled),
+    };
+    let on_abort_state = Arc::clone(&join_state);
+    let on_abort: OnAbort = Arc::new(move || {
+        let mut guard = on_abort_state.lock().unwrap();
+        if let JoinState::Awaited(waker) = mem::replace(&mut *guard, JoinState::Aborted) {
+            waker.wake();
+        }
+    });
+    let task = Box::pin(async move {
+        let value = future.await;
+        let mut guard = join_state.lock().unwrap();
+        if let JoinState::Awaited(waker) = mem::replace(&mut *guard, JoinState::Ready(value)) {
+            waker.wake();
+        }
+    });
+    NEW_TASKS.lock().unwrap().push((task, cancelled, on_abort));
+    join_handle
+}
+
+// In production we'd use AtomicBool instead of Mutex<bool>.
+struct AwakeFlag(Mutex<bool>);
+
+impl AwakeFlag {
+    fn check_and_clear(&self) -> bool {
+        let mut guard = self.0.lock().unwrap();
+        let check = *guard;
+        *guard = false;
+        check
+    }
+}
+
+impl Wake for AwakeFlag {
+    fn wake(self: Arc<Self>) {
+        *self.0.lock().unwrap() = true;
+    }
+}
+
+// A local whose Drop we can observe, to demonstrate that aborting a task really does run the
+// destructors of everything it owns, rather than just leaking its future in place.
+struct PrintOnDrop(&'static str);
+
+impl Drop for PrintOnDrop {
+    fn drop(&mut self) {
+        println!("dropped: {}", self.0);
+    }
+}
+
+async fn job(n: u64) -> u64 {
+    // Held across the .await below, so that aborting this task mid-sleep has to run this guard's
+    // destructor to prove the cancellation isn't just a leaked, forgotten future.
+    let _guard = PrintOnDrop("job guard");
+    sleep(Duration::from_secs(1)).await;
+    n * n
+}
+
+async fn async_main() {
+    let handle = spawn(job(1));
+    let abort_handle = handle.abort_handle();
+    // Abort almost immediately, well before the 1-second sleep would finish on its own.
+    spawn(async move {
+        sleep(Duration::from_millis(50)).await;
+        abort_handle.abort();
+    });
+    match handle.await {
+        Ok(value) => println!("job finished with {value} (unexpected: it should have been aborted)"),
+        Err(Aborted) => println!("job was aborted, as expected, and its guard above was dropped"),
+    }
+}
+
+fn main() {
+    let awake_flag = Arc::new(AwakeFlag(Mutex::new(false)));
+    let waker = Waker::from(Arc::clone(&awake_flag));
+    let mut context = Context::from_waker(&waker);
+    let mut main_task = Box::pin(async_main());
+    let mut other_tasks: Vec<(DynFuture, Arc<AtomicBool>, OnAbort)> = Vec::new();
+    loop {
+        if main_task.as_mut().poll(&mut context).is_ready() {
+            return;
+        }
+        // Poll other tasks and remove any that are Ready or have been aborted. Dropping the
+        // (DynFuture, _, _) tuple here, via retain_mut discarding the failing element, is what
+        // runs the destructors of everything the aborted task owned.
+        let is_pending = |(task, cancelled, on_abort): &mut (DynFuture, Arc<AtomicBool>, OnAbort)| {
+            if cancelled.load(Ordering::Acquire) {
+                on_abort();
+                return false;
+            }
+            task.as_mut().poll(&mut context).is_pending()
+        };
+        other_tasks.retain_mut(is_pending);
+        loop {
+            let Some((mut task, cancelled, on_abort)) = NEW_TASKS.lock().unwrap().pop() else {
+                break;
+            };
+            if cancelled.load(Ordering::Acquire) {
+                on_abort(); // Aborted before it was ever polled once.
+                continue;
+            }
+            if task.as_mut().poll(&mut context).is_pending() {
+                other_tasks.push((task, cancelled, on_abort));
+            }
+        }
+        if awake_flag.check_and_clear() {
+            continue;
+        }
+        let mut wakers_tree = WAKERS.lock().unwrap();
+        if let Some(next_wake) = wakers_tree.keys().next() {
+            thread::sleep(next_wake.saturating_duration_since(Instant::now()));
+        }
+        while let Some(entry) = wakers_tree.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static WAKERS: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wakers_tree = WAKERS.lock().unwrap();
+            let wakers_vec = wakers_tree.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+static NEW_TASKS: Mutex<Vec<DynFuture>> = Mutex::new(Vec::new());
+
+enum JoinState<T> {
+    Unawaited,
+    Awaited(Waker),
+    Ready(T),
+    Done,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut guard = self.state.lock().unwrap();
+        match mem::replace(&mut *guard, JoinState::Done) {
+            JoinState::Ready(value) => Poll::Ready(value),
+            JoinState::Unawaited | JoinState::Awaited(_) => {
+                *guard = JoinState::Awaited(context.waker().clone());
+                Poll::Pending
+            }
+            JoinState::Done => unreachable!("polled again after Ready"),
+        }
+    }
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&join_state),
+    };
+    let task = Box::pin(async move {
+        let value = future.await;
+        let mut guard = join_state.lock().unwrap();
+        if let JoinState::Awaited(waker) = mem::replace(&mut *guard, JoinState::Ready(value)) {
+            waker.wake();
+        }
+    });
+    NEW_TASKS.lock().unwrap().push(task);
+    join_handle
+}
+
+// In production we'd use AtomicBool instead of Mutex<bool>.
+struct AwakeFlag(Mutex<bool>);
+
+impl AwakeFlag {
+    fn check_and_clear(&self) -> bool {
+        let mut guard = self.0.lock().unwrap();
+        let check = *guard;
+        *guard = false;
+        check
+    }
+}
+
+impl Wake for AwakeFlag {
+    fn wake(self: Arc<Self>) {
+        *self.0.lock().unwrap() = true;
+    }
+}
+
+// Modeled on futures::stream::FuturesUnordered: a set of boxed futures that yields each output as
+// soon as it's ready, in completion order, instead of join_all's spawn order. Futures can be
+// pushed in after construction, so a caller can spawn replacement work as each one finishes.
+struct CompletionStream<T> {
+    futures: Vec<Pin<Box<dyn Future<Output = T> + Send>>>,
+}
+
+impl<T> CompletionStream<T> {
+    fn new() -> Self {
+        CompletionStream { futures: Vec::new() }
+    }
+
+    fn push(&mut self, future: impl Future<Output = T> + Send + 'static) {
+        self.futures.push(Box::pin(future));
+    }
+
+    // Poll every not-yet-ready member with the outer context's waker, and return the first output
+    // that's ready, if any. Iterating back-to-front lets us swap_remove without skipping the
+    // future that gets shifted into the index we just vacated.
+    fn poll_next(&mut self, context: &mut Context) -> Poll<Option<T>> {
+        for i in (0..self.futures.len()).rev() {
+            if let Poll::Ready(value) = self.futures[i].as_mut().poll(context) {
+                self.futures.swap_remove(i);
+                return Poll::Ready(Some(value));
+            }
+        }
+        if self.futures.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<T> Future for &mut CompletionStream<T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<T>> {
+        Pin::into_inner(self).poll_next(context)
+    }
+}
+
+async fn job(n: u64) -> u64 {
+    // Jobs finish in reverse order of n, so the completion order visibly differs from spawn order.
+    sleep(Duration::from_millis(100 * (11 - n))).await;
+    n * n
+}
+
+async fn async_main() {
+    println!("Spawn 10 jobs and collect results in completion order, not spawn order.\n");
+    let mut completions = CompletionStream::new();
+    for n in 1..=10 {
+        let handle = spawn(job(n));
+        completions.push(async move { handle.await });
+    }
+    while let Some(result) = (&mut completions).await {
+        println!("collected result {result}");
+    }
+}
+
+fn main() {
+    let awake_flag = Arc::new(AwakeFlag(Mutex::new(false)));
+    let waker = Waker::from(Arc::clone(&awake_flag));
+    let mut context = Context::from_waker(&waker);
+    let mut main_task = Box::pin(async_main());
+    let mut other_tasks: Vec<DynFuture> = Vec::new();
+    loop {
+        if main_task.as_mut().poll(&mut context).is_ready() {
+            return;
+        }
+        let is_pending = |task: &mut DynFuture| task.as_mut().poll(&mut context).is_pending();
+        other_tasks.retain_mut(is_pending);
+        loop {
+            let Some(mut task) = NEW_TASKS.lock().unwrap().pop() else {
+                break;
+            };
+            if task.as_mut().poll(&mut context).is_pending() {
+                other_tasks.push(task);
+            }
+        }
+        if awake_flag.check_and_clear() {
+            continue;
+        }
+        let mut wakers_tree = WAKERS.lock().unwrap();
+        if let Some(next_wake) = wakers_tree.keys().next() {
+            thread::sleep(next_wake.saturating_duration_since(Instant::now()));
+        }
+        while let Some(entry) = wakers_tree.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static WAKERS: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wakers_tree = WAKERS.lock().unwrap();
+            let wakers_vec = wakers_tree.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+static NEW_TASKS: Mutex<Vec<DynFuture>> = Mutex::new(Vec::new());
+
+enum JoinState<T> {
+    Unawaited,
+    Awaited(Waker),
+    Ready(T),
+    Done,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut guard = self.state.lock().unwrap();
+        match mem::replace(&mut *guard, JoinState::Done) {
+            JoinState::Ready(value) => Poll::Ready(value),
+            JoinState::Unawaited | JoinState::Awaited(_) => {
+                *guard = JoinState::Awaited(context.waker().clone());
+                Poll::Pending
+            }
+            JoinState::Done => unreachable!("polled again after Ready"),
+        }
+    }
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&join_state),
+    };
+    let task = Box::pin(async move {
+        let value = future.await;
+        let mut guard = join_state.lock().unwrap();
+        if let JoinState::Awaited(waker) = mem::replace(&mut *guard, JoinState::Ready(value)) {
+            waker.wake();
+        }
+    });
+    NEW_TASKS.lock().unwrap().push(task);
+    join_handle
+}
+
+// In production we'd use AtomicBool instead of Mutex<bool>.
+struct AwakeFlag(Mutex<bool>);
+
+impl AwakeFlag {
+    fn check_and_clear(&self) -> bool {
+        let mut guard = self.0.lock().unwrap();
+        let check = *guard;
+        *guard = false;
+        check
+    }
+}
+
+impl Wake for AwakeFlag {
+    fn wake(self: Arc<Self>) {
+        *self.0.lock().unwrap() = true;
+    }
+}
+
+// Unlike tokio::select!, whose arms are a fixed count written out at the call site, this accepts
+// an arbitrary, growable Vec: useful for selecting over e.g. N listeners or N jobs decided at
+// runtime. Polls every future in turn, and on the first Poll::Ready, resolves to the output, the
+// index that completed, and the rest of the Vec (with that future removed) so the caller can
+// select again. The Vec has to live inside the poll_fn closure (captured by move) rather than
+// being reconstructed on every call, or else a Pending poll would silently drop every future's
+// progress along with its half-finished .await state.
+async fn select_all<T>(
+    mut futures: Vec<Pin<Box<dyn Future<Output = T> + Send>>>,
+) -> (T, usize, Vec<Pin<Box<dyn Future<Output = T> + Send>>>) {
+    std::future::poll_fn(move |context| {
+        for i in 0..futures.len() {
+            if let Poll::Ready(value) = futures[i].as_mut().poll(context) {
+                let remaining = futures
+                    .drain(..)
+                    .enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .map(|(_, future)| future)
+                    .collect();
+                return Poll::Ready((value, i, remaining));
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+async fn job(n: u64) -> u64 {
+    sleep(Duration::from_millis(100 * (11 - n))).await;
+    n * n
+}
+
+async fn async_main() {
+    println!("Select over a runtime-sized Vec of jobs, one winner at a time.\n");
+    let mut futures: Vec<Pin<Box<dyn Future<Output = u64> + Send>>> =
+        (1..=5).map(|n| Box::pin(job(n)) as _).collect();
+    while !futures.is_empty() {
+        let (value, index, remaining) = select_all(futures).await;
+        println!("job at index {index} finished first, with value {value}");
+        futures = remaining;
+    }
+}
+
+fn main() {
+    let awake_flag = Arc::new(AwakeFlag(Mutex::new(false)));
+    let waker = Waker::from(Arc::clone(&awake_flag));
+    let mut context = Context::from_waker(&waker);
+    let mut main_task = Box::pin(async_main());
+    let mut other_tasks: Vec<DynFuture> = Vec::new();
+    loop {
+        if main_task.as_mut().poll(&mut context).is_ready() {
+            return;
+        }
+        let is_pending = |task: &mut DynFuture| task.as_mut().poll(&mut context).is_pending();
+        other_tasks.retain_mut(is_pending);
+        loop {
+            let Some(mut task) = NEW_TASKS.lock().unwrap().pop() else {
+                break;
+            };
+            if task.as_mut().poll(&mut context).is_pending() {
+                other_tasks.push(task);
+            }
+        }
+        if awake_flag.check_and_clear() {
+            continue;
+        }
+        let mut wakers_tree = WAKERS.lock().unwrap();
+        if let Some(next_wake) = wakers_tree.keys().next() {
+            thread::sleep(next_wake.saturating_duration_since(Instant::now()));
+        }
+        while let Some(entry) = wakers_tree.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use crossbeam_channel::{unbounded, Receiver, Sender, TryRecvError};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static WAKE_TIMES: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            let wakers_vec = wake_times.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+// Every Runnable that's ready to make progress goes through this channel, instead of every task
+// getting polled on every wakeup. This is the "ready queue" that a real executor (smol, juliex)
+// builds on top of async-task.
+static TASK_SENDER: OnceLock<Sender<Arc<Task>>> = OnceLock::new();
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+// A Task owns its future and knows how to re-enqueue itself. Waking a Task's Waker just sends
+// the Arc back down the channel; it doesn't touch any other task.
+struct Task {
+    future: Mutex<Option<DynFuture>>,
+    sender: Sender<Arc<Task>>,
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        // If the queue is gone (we've already exited the executor), there's nothing to do.
+        let _ = self.sender.send(Arc::clone(self));
+    }
+}
+
+fn poll_task(task: &Arc<Task>) {
+    let waker = Waker::from(Arc::clone(task));
+    let mut context = Context::from_waker(&waker);
+    let mut future_slot = task.future.lock().unwrap();
+    // The future might already be gone if it was woken twice before being polled once.
+    let Some(future) = future_slot.as_mut() else {
+        return;
+    };
+    if future.as_mut().poll(&mut context).is_ready() {
+        *future_slot = None;
+    }
+}
+
+enum JoinState<T> {
+    Unawaited,
+    Awaited(Waker),
+    Ready(T),
+    Done,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut guard = self.state.lock().unwrap();
+        match mem::replace(&mut *guard, JoinState::Done) {
+            JoinState::Ready(value) => Poll::Ready(value),
+            JoinState::Unawaited | JoinState::Awaited(_) => {
+                *guard = JoinState::Awaited(context.waker().clone());
+                Poll::Pending
+            }
+            JoinState::Done => unreachable!("polled again after Ready"),
+        }
+    }
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let sender = TASK_SENDER.get().expect("spawn called before the executor started").clone();
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&join_state),
+    };
+    let wrapped = Box::pin(async move {
+        let value = future.await;
+        let mut guard = join_state.lock().unwrap();
+        if let JoinState::Awaited(waker) = mem::replace(&mut *guard, JoinState::Ready(value)) {
+            waker.wake();
+        }
+    });
+    let task = Arc::new(Task {
+        future: Mutex::new(Some(wrapped)),
+        sender: sender.clone(),
+    });
+    sender.send(task).expect("queue closed");
+    join_handle
+}
+
+async fn foo(n: u64) {
+    println!("start {n}");
+    sleep(Duration::from_secs(1)).await;
+    println!("end {n}");
+}
+
+async fn async_main() {
+    let mut task_handles = Vec::new();
+    for n in 1..=10 {
+        task_handles.push(spawn(foo(n)));
+    }
+    for handle in task_handles {
+        handle.await;
+    }
+}
+
+fn main() {
+    let (sender, receiver): (Sender<Arc<Task>>, Receiver<Arc<Task>>) = unbounded();
+    TASK_SENDER.set(sender).expect("set called twice");
+
+    // The executor is done once async_main's own task finishes. We can't just check that the
+    // ready queue is empty, because a task might currently be asleep in WAKE_TIMES.
+    let done = Arc::new(AtomicBool::new(false));
+    let done_clone = Arc::clone(&done);
+    spawn(async move {
+        async_main().await;
+        done_clone.store(true, Ordering::SeqCst);
+    });
+
+    while !done.load(Ordering::SeqCst) {
+        match receiver.try_recv() {
+            Ok(task) => poll_task(&task),
+            Err(TryRecvError::Empty) => {
+                // Nothing is ready right now. Sleep until the next timer fires, exactly like the
+                // reactor-only loop, and then wake whichever Sleep futures have expired. Waking
+                // them sends their Tasks back onto this same queue.
+                let mut wake_times = WAKE_TIMES.lock().unwrap();
+                if let Some(next_wake) = wake_times.keys().next() {
+                    thread::sleep(next_wake.saturating_duration_since(Instant::now()));
+                }
+                while let Some(entry) = wake_times.first_entry() {
+                    if *entry.key() <= Instant::now() {
+                        entry.remove().into_iter().for_each(Waker::wake);
+                    } else {
+                        break;
+                    }
+                }
+            }
+            Err(TryRecvError::Disconnected) => unreachable!("we're still holding a Sender"),
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use stream::{from_fn, Stream, StreamExt};
+
+mod stream {
+    use super::*;
+
+    // The async analogue of Future: instead of resolving once, it can be polled repeatedly,
+    // yielding Some(item) any number of times before finally settling on None.
+    pub trait Stream {
+        type Item;
+
+        fn poll_next(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<Self::Item>>;
+    }
+
+    // Mirrors std::future::poll_fn: lets a plain closure stand in for a full Stream impl, for the
+    // common case where there's no extra state beyond what the closure itself captures.
+    pub struct FromFn<F> {
+        f: F,
+    }
+
+    // The closure never moves itself around internally, so FromFn never needs to be pinned in
+    // place; this unconditional impl is the same one std::future::poll_fn's PollFn uses.
+    impl<F> Unpin for FromFn<F> {}
+
+    impl<T, F> Stream for FromFn<F>
+    where
+        F: FnMut(&mut Context) -> Poll<Option<T>>,
+    {
+        type Item = T;
+
+        fn poll_next(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<T>> {
+            (self.f)(context)
+        }
+    }
+
+    pub fn from_fn<T, F: FnMut(&mut Context) -> Poll<Option<T>>>(f: F) -> FromFn<F> {
+        FromFn { f }
+    }
+
+    // Extension trait providing `.next()`, the same way futures::StreamExt does, so callers can
+    // `.await` a Stream item by item instead of calling poll_next directly.
+    pub trait StreamExt: Stream {
+        fn next(&mut self) -> Next<Self>
+        where
+            Self: Sized + Unpin,
+        {
+            Next { stream: self }
+        }
+    }
+
+    impl<S: Stream> StreamExt for S {}
+
+    pub struct Next<'a, S: ?Sized> {
+        stream: &'a mut S,
+    }
+
+    impl<S: Stream + Unpin> Future for Next<'_, S> {
+        type Output = Option<S::Item>;
+
+        fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<S::Item>> {
+            let this = Pin::into_inner(self);
+            Pin::new(&mut *this.stream).poll_next(context)
+        }
+    }
+}
+
+static WAKERS: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wakers_tree = WAKERS.lock().unwrap();
+            let wakers_vec = wakers_tree.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+static NEW_TASKS: Mutex<Vec<DynFuture>> = Mutex::new(Vec::new());
+
+enum JoinState<T> {
+    Unawaited,
+    Awaited(Waker),
+    Ready(T),
+    Done,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut guard = self.state.lock().unwrap();
+        match mem::replace(&mut *guard, JoinState::Done) {
+            JoinState::Ready(value) => Poll::Ready(value),
+            JoinState::Unawaited | JoinState::Awaited(_) => {
+                *guard = JoinState::Awaited(context.waker().clone());
+                Poll::Pending
+            }
+            JoinState::Done => unreachable!("polled again after Ready"),
+        }
+    }
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&join_state),
+    };
+    let task = Box::pin(async move {
+        let value = future.await;
+        let mut guard = join_state.lock().unwrap();
+        if let JoinState::Awaited(waker) = mem::replace(&mut *guard, JoinState::Ready(value)) {
+            waker.wake();
+        }
+    });
+    NEW_TASKS.lock().unwrap().push(task);
+    join_handle
+}
+
+// In production we'd use AtomicBool instead of Mutex<bool>.
+struct AwakeFlag(Mutex<bool>);
+
+impl AwakeFlag {
+    fn check_and_clear(&self) -> bool {
+        let mut guard = self.0.lock().unwrap();
+        let check = *guard;
+        *guard = false;
+        check
+    }
+}
+
+impl Wake for AwakeFlag {
+    fn wake(self: Arc<Self>) {
+        *self.0.lock().unwrap() = true;
+    }
+}
+
+// The future half of a yield point: returns Pending the first time it's polled (after the caller
+// has already stashed the yielded value in the shared slot below), then Ready(()) the next time,
+// the same registered-flag trick Async<T>::readable/writable use in client_server_async_adapter.rs
+// to turn "wait for one more poll" into a real suspend point instead of a busy loop.
+struct YieldPoint {
+    yielded: bool,
+}
+
+impl Future for YieldPoint {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, _context: &mut Context) -> Poll<()> {
+        if mem::replace(&mut self.yielded, true) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+// Handle passed into a generator function, letting it hand an item to its Stream without losing
+// its place: the item goes into a slot shared with GenStream, and the generator suspends at a
+// YieldPoint until GenStream polls it again.
+struct YieldContext<T> {
+    slot: Arc<Mutex<Option<T>>>,
+}
+
+impl<T> YieldContext<T> {
+    async fn yield_value(&self, value: T) {
+        *self.slot.lock().unwrap() = Some(value);
+        YieldPoint { yielded: false }.await;
+    }
+}
+
+// A Stream built from an async fn that calls YieldContext::yield_value any number of times before
+// finishing. Polling the underlying future either runs it to completion (Ready(()), meaning the
+// stream is done) or suspends it at a YieldPoint (Pending) -- and a Pending with something in the
+// slot means an item was just yielded, while a Pending with an empty slot means the generator is
+// off awaiting something else entirely, like sleep(), and hasn't yielded yet.
+struct GenStream<T> {
+    slot: Arc<Mutex<Option<T>>>,
+    future: Pin<Box<dyn Future<Output = ()> + Send>>,
+    done: bool,
+}
+
+impl<T> Stream for GenStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<T>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        match self.future.as_mut().poll(context) {
+            Poll::Ready(()) => {
+                self.done = true;
+                Poll::Ready(None)
+            }
+            Poll::Pending => match self.slot.lock().unwrap().take() {
+                Some(item) => Poll::Ready(Some(item)),
+                None => Poll::Pending,
+            },
+        }
+    }
+}
+
+fn from_generator<T, F, Fut>(generator: F) -> GenStream<T>
+where
+    F: FnOnce(YieldContext<T>) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+    T: Send + 'static,
+{
+    let slot = Arc::new(Mutex::new(None));
+    let context = YieldContext {
+        slot: Arc::clone(&slot),
+    };
+    GenStream {
+        slot,
+        future: Box::pin(generator(context)),
+        done: false,
+    }
+}
+
+// Rewritten from tasks_join_handle.rs's `job`, which could only report a single final result:
+// this one streams its progress instead, yielding a percentage on every step rather than leaving
+// async_main to wait in the dark until the whole computation finishes.
+fn job_with_progress(n: u64) -> GenStream<u64> {
+    from_generator(move |yield_context| async move {
+        for percent in 0..=100u64 {
+            sleep(Duration::from_millis(10)).await;
+            yield_context.yield_value(percent).await;
+        }
+        println!("job {n} finished");
+    })
+}
+
+async fn async_main() {
+    println!("Stream progress ticks from a single job instead of just its final result.\n");
+    let mut progress = job_with_progress(1);
+    while let Some(percent) = progress.next().await {
+        if percent % 10 == 0 {
+            println!("job 1 progress: {percent}%");
+        }
+    }
+
+    // A from_fn stream, for contrast: no generator, no yield points, just a closure polled
+    // directly, the way std::future::poll_fn stands in for a one-off Future impl.
+    let mut countdown = 3u64;
+    let mut ticker = from_fn(move |_context| {
+        if countdown == 0 {
+            Poll::Ready(None)
+        } else {
+            countdown -= 1;
+            Poll::Ready(Some(countdown))
+        }
+    });
+    while let Some(tick) = ticker.next().await {
+        println!("countdown: {tick}");
+    }
+}
+
+fn main() {
+    let awake_flag = Arc::new(AwakeFlag(Mutex::new(false)));
+    let waker = Waker::from(Arc::clone(&awake_flag));
+    let mut context = Context::from_waker(&waker);
+    let mut main_task = Box::pin(async_main());
+    let mut other_tasks: Vec<DynFuture> = Vec::new();
+    loop {
+        if main_task.as_mut().poll(&mut context).is_ready() {
+            return;
+        }
+        let is_pending = |task: &mut DynFuture| task.as_mut().poll(&mut context).is_pending();
+        other_tasks.retain_mut(is_pending);
+        loop {
+            let Some(mut task) = NEW_TASKS.lock().unwrap().pop() else {
+                break;
+            };
+            if task.as_mut().poll(&mut context).is_pending() {
+                other_tasks.push(task);
+            }
+        }
+        if awake_flag.check_and_clear() {
+            continue;
+        }
+        let mut wakers_tree = WAKERS.lock().unwrap();
+        if let Some(next_wake) = wakers_tree.keys().next() {
+            thread::sleep(next_wake.saturating_duration_since(Instant::now()));
+        }
+        while let Some(entry) = wakers_tree.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+use crossbeam_utils::sync::{Parker, Unparker};
+use rand::prelude::*;
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static WAKE_TIMES: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            let wakers_vec = wake_times.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+type DynFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+// Replaces the single NEW_TASKS Vec with a global injector, one Stealer per worker, and one
+// Unparker per worker: the same three statics client_server_work_stealing.rs uses for its
+// reactor-driven tasks. There's no I/O reactor here, just CPU-bound work and sleep()-driven
+// wakeups, but the scheduling story is identical.
+static INJECTOR: OnceLock<Injector<Arc<Task>>> = OnceLock::new();
+static STEALERS: OnceLock<Vec<Stealer<Arc<Task>>>> = OnceLock::new();
+static UNPARKERS: OnceLock<Vec<Unparker>> = OnceLock::new();
+
+fn injector() -> &'static Injector<Arc<Task>> {
+    INJECTOR.get_or_init(Injector::new)
+}
+
+struct Task {
+    future: Mutex<Option<DynFuture>>,
+    // Guards against double-enqueueing a task that's woken more than once before it's next
+    // polled. Cleared right before polling, so a wakeup that arrives *during* that poll still
+    // results in exactly one re-enqueue.
+    scheduled: AtomicBool,
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.scheduled.swap(true, Ordering::AcqRel) {
+            injector().push(Arc::clone(self));
+            if let Some(unparkers) = UNPARKERS.get() {
+                for unparker in unparkers {
+                    unparker.unpark();
+                }
+            }
+        }
+    }
+}
+
+fn poll_task(task: &Arc<Task>, context: &mut Context) {
+    task.scheduled.store(false, Ordering::Release);
+    let mut future_slot = task.future.lock().unwrap();
+    // The future is already gone if the task was woken during the poll that finished it.
+    let Some(future) = future_slot.as_mut() else {
+        return;
+    };
+    if future.as_mut().poll(context).is_ready() {
+        *future_slot = None;
+    }
+}
+
+enum JoinState<T> {
+    Unawaited,
+    Awaited(Waker),
+    Ready(T),
+    Done,
+}
+
+struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut guard = self.state.lock().unwrap();
+        // Use JoinState::Done as a placeholder, to take ownership of T.
+        match mem::replace(&mut *guard, JoinState::Done) {
+            JoinState::Ready(value) => Poll::Ready(value),
+            JoinState::Unawaited | JoinState::Awaited(_) => {
+                // Replace the previous Waker, if any. We only need the most recent one.
+                *guard = JoinState::Awaited(context.waker().clone());
+                Poll::Pending
+            }
+            JoinState::Done => unreachable!("polled again after Ready"),
+        }
+    }
+}
+
+async fn wrap_with_join_state<F: Future>(future: F, join_state: Arc<Mutex<JoinState<F::Output>>>) {
+    let value = future.await;
+    let mut guard = join_state.lock().unwrap();
+    if let JoinState::Awaited(waker) = &*guard {
+        waker.wake_by_ref();
+    }
+    *guard = JoinState::Ready(value)
+}
+
+fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let join_state = Arc::new(Mutex::new(JoinState::Unawaited));
+    let join_handle = JoinHandle {
+        state: Arc::clone(&join_state),
+    };
+    let wrapped = Box::pin(wrap_with_join_state(future, join_state));
+    let task = Arc::new(Task {
+        future: Mutex::new(Some(wrapped)),
+        scheduled: AtomicBool::new(true),
+    });
+    injector().push(task);
+    if let Some(unparkers) = UNPARKERS.get() {
+        for unparker in unparkers {
+            unparker.unpark();
+        }
+    }
+    join_handle
+}
+
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+// Each worker pops from its own local deque first, then the shared injector (taking a whole
+// batch at once to amortize the lock), and only then tries to steal from a sibling. Same
+// three-tier search as client_server_work_stealing.rs.
+fn find_task(local: &Worker<Arc<Task>>) -> Option<Arc<Task>> {
+    local.pop().or_else(|| {
+        std::iter::repeat_with(|| {
+            injector()
+                .steal_batch_and_pop(local)
+                .or_else(|| STEALERS.get().unwrap().iter().map(Stealer::steal).collect())
+        })
+        .find(|s| !s.is_retry())
+        .and_then(Steal::success)
+    })
+}
+
+fn worker_loop(local: Worker<Arc<Task>>, parker: Parker) {
+    let waker_of = |task: &Arc<Task>| Waker::from(Arc::clone(task));
+    while !SHUTDOWN.load(Ordering::Acquire) {
+        match find_task(&local) {
+            Some(task) => {
+                let waker = waker_of(&task);
+                let mut context = Context::from_waker(&waker);
+                poll_task(&task, &mut context);
+            }
+            None => parker.park_timeout(Duration::from_millis(10)),
+        }
+    }
+}
+
+// A dedicated thread to fire due timers, since nothing else is polling WAKE_TIMES now that tasks
+// are spread across worker threads.
+fn timer_loop() {
+    while !SHUTDOWN.load(Ordering::Acquire) {
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        let timeout = if let Some(time) = wake_times.keys().next() {
+            time.saturating_duration_since(Instant::now()).min(Duration::from_millis(100))
+        } else {
+            Duration::from_millis(100) // wake up periodically anyway, to notice SHUTDOWN
+        };
+        drop(wake_times);
+        thread::sleep(timeout);
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        while let Some(entry) = wake_times.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+// A parallel analogue of basketball_futures.rs. That benchmark's own comment notes that multiple
+// futures on the same task are generally stuck on the same thread, so its throughput stops
+// scaling once a single pass_basketballs_around future saturates one hardware thread. Spawning one
+// task per ball here, on top of the work-stealing pool above, lets passing actually spread across
+// cores instead.
+const BALL_WORKERS: usize = 8;
+const BUSY_TIME: Duration = Duration::from_micros(1);
+const BENCH_DURATION: Duration = Duration::from_millis(200);
+
+struct Ball {
+    passes: u64,
+}
+
+// A small async-aware mailbox: one per worker task, with a waker slot so send() can wake up
+// whichever task is parked waiting on an empty mailbox.
+struct Mailbox {
+    queue: Mutex<VecDeque<Ball>>,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Mailbox {
+    fn new() -> Arc<Mailbox> {
+        Arc::new(Mailbox {
+            queue: Mutex::new(VecDeque::new()),
+            waker: Mutex::new(None),
+        })
+    }
+
+    fn send(&self, ball: Ball) {
+        self.queue.lock().unwrap().push_back(ball);
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+
+    async fn recv(&self) -> Ball {
+        std::future::poll_fn(|context| match self.queue.lock().unwrap().pop_front() {
+            Some(ball) => Poll::Ready(ball),
+            None => {
+                *self.waker.lock().unwrap() = Some(context.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+async fn pass_basketballs_around(
+    mailbox: Arc<Mailbox>,
+    mailboxes: Arc<Vec<Arc<Mailbox>>>,
+    trash: Arc<Mailbox>,
+    passes_per_ball: u64,
+) {
+    loop {
+        let mut ball = mailbox.recv().await;
+        let busy_start = Instant::now();
+        while Instant::now() < busy_start + BUSY_TIME {}
+        if ball.passes < passes_per_ball {
+            ball.passes += 1;
+            let target = mailboxes.choose(&mut rand::thread_rng()).unwrap();
+            target.send(ball);
+        } else {
+            trash.send(ball);
+        }
+    }
+}
+
+async fn bench() -> Duration {
+    let total_passes = (BENCH_DURATION.as_nanos() / BUSY_TIME.as_nanos()) as u64;
+    let passes_per_ball = total_passes / BALL_WORKERS as u64;
+    let mailboxes: Arc<Vec<Arc<Mailbox>>> =
+        Arc::new((0..BALL_WORKERS).map(|_| Mailbox::new()).collect());
+    let trash = Mailbox::new();
+    for mailbox in mailboxes.iter() {
+        mailbox.send(Ball { passes: 0 });
+    }
+    let worker_handles: Vec<_> = mailboxes
+        .iter()
+        .map(|mailbox| {
+            spawn(pass_basketballs_around(
+                Arc::clone(mailbox),
+                Arc::clone(&mailboxes),
+                Arc::clone(&trash),
+                passes_per_ball,
+            ))
+        })
+        .collect();
+    let start = Instant::now();
+    for _ in 0..BALL_WORKERS {
+        trash.recv().await;
+    }
+    let elapsed = Instant::now() - start;
+    // Workers loop forever passing balls and never finish on their own; dropping these
+    // JoinHandles just detaches them instead of waiting for them.
+    drop(worker_handles);
+    elapsed
+}
+
+async fn async_main() {
+    println!("Number of CPUs:     {}", num_cpus::get());
+    println!("Number of workers:  {BALL_WORKERS}");
+    println!("Busy time per pass: {BUSY_TIME:?}\n");
+    let elapsed = bench().await;
+    let total_passes = (BENCH_DURATION.as_nanos() / BUSY_TIME.as_nanos()) as u64;
+    let throughput = total_passes / elapsed.as_millis().max(1) as u64;
+    println!("throughput: {throughput} passes / millisecond, across {} cores", num_cpus::get());
+}
+
+fn main() {
+    let num_workers = num_cpus::get();
+    let workers: Vec<Worker<Arc<Task>>> = (0..num_workers).map(|_| Worker::new_fifo()).collect();
+    STEALERS
+        .set(workers.iter().map(Worker::stealer).collect())
+        .unwrap();
+    let parkers: Vec<Parker> = (0..num_workers).map(|_| Parker::new()).collect();
+    UNPARKERS
+        .set(parkers.iter().map(Parker::unparker).cloned().collect())
+        .unwrap();
+
+    let worker_threads: Vec<_> = workers
+        .into_iter()
+        .zip(parkers)
+        .map(|(local, parker)| thread::spawn(move || worker_loop(local, parker)))
+        .collect();
+    let timer_thread = thread::spawn(timer_loop);
+
+    // A one-slot channel just to get async_main back out to the main thread once it's done.
+    let (done_sender, done_receiver) = crossbeam_channel::bounded(1);
+    spawn(async move {
+        async_main().await;
+        done_sender.send(()).expect("main thread is waiting");
+    });
+    done_receiver.recv().expect("async_main task panicked");
+
+    SHUTDOWN.store(true, Ordering::Release);
+    for unparker in UNPARKERS.get().unwrap() {
+        unparker.unpark();
+    }
+    for handle in worker_threads {
+        handle.join().expect("worker thread panicked");
+    }
+    timer_thread.join().expect("timer thread panicked");
+}
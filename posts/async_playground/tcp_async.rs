@@ -0,0 +1,226 @@
+use polling::{Event, Events, PollMode, Poller};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::io::prelude::*;
+use std::net::{TcpListener, TcpStream};
+use std::os::fd::{AsFd, AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::{Mutex, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+static WAKE_TIMES: Mutex<BTreeMap<Instant, Vec<Waker>>> = Mutex::new(BTreeMap::new());
+
+struct Sleep {
+    wake_time: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.wake_time {
+            Poll::Ready(())
+        } else {
+            let mut wake_times = WAKE_TIMES.lock().unwrap();
+            let wakers_vec = wake_times.entry(self.wake_time).or_default();
+            wakers_vec.push(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let wake_time = Instant::now() + duration;
+    Sleep { wake_time }
+}
+
+// The reactor is a single epoll/kqueue instance (via the `polling` crate) shared by every
+// Async<T>. Each registered fd gets its raw fd as its event key, which is unique because a given
+// fd can only be registered once at a time.
+fn reactor() -> &'static Poller {
+    static REACTOR: OnceLock<Poller> = OnceLock::new();
+    REACTOR.get_or_init(|| Poller::new().expect("failed to create reactor"))
+}
+
+static READERS: Mutex<BTreeMap<RawFd, Waker>> = Mutex::new(BTreeMap::new());
+static WRITERS: Mutex<BTreeMap<RawFd, Waker>> = Mutex::new(BTreeMap::new());
+
+// Wraps any raw-fd-based I/O type and registers it with the reactor, so that reads, writes, and
+// accepts can be awaited instead of blocking a whole thread.
+struct Async<T: AsRawFd> {
+    inner: T,
+}
+
+impl<T: AsRawFd> Async<T> {
+    fn new(inner: T) -> io::Result<Self> {
+        // Oneshot mode: each readiness event needs to be re-armed with `modify` after it fires.
+        unsafe {
+            reactor().add_with_mode(inner.as_raw_fd(), Event::none(inner.as_raw_fd() as usize), PollMode::Oneshot)?;
+        }
+        Ok(Self { inner })
+    }
+
+    fn poll_readable(&self, context: &mut Context) -> Poll<io::Result<()>> {
+        let fd = self.inner.as_raw_fd();
+        READERS.lock().unwrap().insert(fd, context.waker().clone());
+        match reactor().modify(self.inner.as_fd(), Event::readable(fd as usize)) {
+            Ok(()) => Poll::Pending,
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+
+    fn poll_writable(&self, context: &mut Context) -> Poll<io::Result<()>> {
+        let fd = self.inner.as_raw_fd();
+        WRITERS.lock().unwrap().insert(fd, context.waker().clone());
+        match reactor().modify(self.inner.as_fd(), Event::writable(fd as usize)) {
+            Ok(()) => Poll::Pending,
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+impl Async<TcpListener> {
+    fn bind(address: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Self::new(listener)
+    }
+
+    async fn accept(&self) -> io::Result<(Async<TcpStream>, std::net::SocketAddr)> {
+        loop {
+            match self.inner.accept() {
+                Ok((stream, addr)) => {
+                    stream.set_nonblocking(true)?;
+                    return Ok((Async::new(stream)?, addr));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    AcceptReady { async_listener: self }.await?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+struct AcceptReady<'a> {
+    async_listener: &'a Async<TcpListener>,
+}
+
+impl<'a> Future for AcceptReady<'a> {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        self.async_listener.poll_readable(context)
+    }
+}
+
+impl Async<TcpStream> {
+    async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.inner.read(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    ReadReady { async_stream: self }.await?;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            match self.inner.write(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    WriteReady { async_stream: self }.await?;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+struct ReadReady<'a> {
+    async_stream: &'a Async<TcpStream>,
+}
+
+impl<'a> Future for ReadReady<'a> {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        self.async_stream.poll_readable(context)
+    }
+}
+
+struct WriteReady<'a> {
+    async_stream: &'a Async<TcpStream>,
+}
+
+impl<'a> Future for WriteReady<'a> {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        self.async_stream.poll_writable(context)
+    }
+}
+
+async fn foo_response(n: u64, mut socket: Async<TcpStream>) -> io::Result<()> {
+    let start_msg = format!("start {n}\n");
+    socket.write(start_msg.as_bytes()).await?;
+    sleep(Duration::from_secs(1)).await;
+    let end_msg = format!("end {n}\n");
+    socket.write(end_msg.as_bytes()).await?;
+    Ok(())
+}
+
+async fn server_main(listener: Async<TcpListener>) -> io::Result<()> {
+    let mut n = 1;
+    loop {
+        let (socket, _addr) = listener.accept().await?;
+        println!("accepted connection {n}");
+        foo_response(n, socket).await?;
+        n += 1;
+    }
+}
+
+fn main() -> io::Result<()> {
+    let listener = Async::bind("0.0.0.0:8000")?;
+    let mut server_task = Box::pin(server_main(listener));
+    let waker = futures::task::noop_waker();
+    let mut context = Context::from_waker(&waker);
+    loop {
+        if server_task.as_mut().poll(&mut context).is_ready() {
+            return Ok(());
+        }
+        // All tasks are either sleeping or waiting on I/O. Block in the reactor's event_wait,
+        // using the earliest WAKE_TIMES entry (if any) as the timeout, exactly like the
+        // libc::poll loop in client_server.rs but generalized to an epoll/kqueue backend.
+        let mut wake_times = WAKE_TIMES.lock().unwrap();
+        let timeout = wake_times
+            .keys()
+            .next()
+            .map(|time| time.saturating_duration_since(Instant::now()));
+        let mut events = Events::new();
+        reactor().wait(&mut events, timeout)?;
+        for event in events.iter() {
+            let fd = event.key as RawFd;
+            if event.readable {
+                if let Some(waker) = READERS.lock().unwrap().remove(&fd) {
+                    waker.wake();
+                }
+            }
+            if event.writable {
+                if let Some(waker) = WRITERS.lock().unwrap().remove(&fd) {
+                    waker.wake();
+                }
+            }
+        }
+        while let Some(entry) = wake_times.first_entry() {
+            if *entry.key() <= Instant::now() {
+                entry.remove().into_iter().for_each(Waker::wake);
+            } else {
+                break;
+            }
+        }
+    }
+}
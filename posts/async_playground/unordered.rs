@@ -0,0 +1,63 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::sleep;
+
+// Unlike `join_all`, which only completes once every child is done, `Unordered` hands back
+// children one at a time as soon as they finish, and lets the caller push new children in after
+// polling has already begun. This is the shape you want for "start N jobs, and every time one
+// finishes, maybe start a replacement."
+struct Unordered<F: Future> {
+    children: Vec<Pin<Box<F>>>,
+}
+
+impl<F: Future> Unordered<F> {
+    fn new() -> Self {
+        Self { children: Vec::new() }
+    }
+
+    fn push(&mut self, future: F) {
+        self.children.push(Box::pin(future));
+    }
+
+    async fn next(&mut self) -> Option<F::Output> {
+        std::future::poll_fn(|context| self.poll_next(context)).await
+    }
+
+    fn poll_next(&mut self, context: &mut Context) -> Poll<Option<F::Output>> {
+        if self.children.is_empty() {
+            return Poll::Ready(None);
+        }
+        for i in 0..self.children.len() {
+            if let Poll::Ready(output) = self.children[i].as_mut().poll(context) {
+                // Order doesn't matter here, so swap_remove avoids shifting the rest down.
+                self.children.swap_remove(i);
+                return Poll::Ready(Some(output));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+async fn wait(name: u64, ms: u64) -> u64 {
+    sleep(Duration::from_millis(ms)).await;
+    name
+}
+
+#[tokio::main]
+async fn main() {
+    let mut jobs = Unordered::new();
+    jobs.push(wait(1, 300));
+    jobs.push(wait(2, 100));
+    jobs.push(wait(3, 200));
+    let mut spawned = 3;
+    while let Some(finished) = jobs.next().await {
+        println!("job {finished} finished");
+        // Replace each finished job with one more, up to a total of 6.
+        if spawned < 6 {
+            spawned += 1;
+            jobs.push(wait(spawned, 150));
+        }
+    }
+}
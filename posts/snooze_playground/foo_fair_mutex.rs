@@ -0,0 +1,141 @@
+use std::cell::UnsafeCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+use tokio::select;
+
+// `tokio::sync::Mutex` (and `futures::lock::Mutex`) are unfair: a task that drops the guard and
+// immediately asks for it again can barge ahead of another task that's already been waiting. This
+// Mutex instead keeps a FIFO queue of waiters, so the guard always goes to whoever asked for it
+// first. Fairness doesn't get `main` below past the `foo_select_loop` deadlock: `future1` holds the
+// guard but isn't polled while the select! arm awaits the second `foo()`, so the guard is never
+// released and that `foo()` waits forever.
+struct FairMutex<T> {
+    locked: AtomicBool,
+    waiters: Mutex<VecDeque<Waker>>,
+    value: UnsafeCell<T>,
+}
+
+// Safety: `value` is only ever accessed through a FairMutexGuard, and FairMutex's own logic
+// guarantees at most one guard exists at a time.
+unsafe impl<T: Send> Sync for FairMutex<T> {}
+
+impl<T> FairMutex<T> {
+    fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            waiters: Mutex::new(VecDeque::new()),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    fn lock(&self) -> Lock<T> {
+        Lock {
+            mutex: self,
+            queued: false,
+        }
+    }
+
+    // Try to take the lock, but only if we're not stuck behind another waiter.
+    fn try_acquire(&self, waker: &Waker, already_queued: bool) -> bool {
+        let mut waiters = self.waiters.lock().unwrap();
+        let at_front =
+            (!already_queued && waiters.is_empty()) || waiters.front().is_some_and(|w| w.will_wake(waker));
+        if at_front && !self.locked.swap(true, Ordering::Acquire) {
+            if already_queued {
+                waiters.pop_front();
+            }
+            true
+        } else {
+            false
+        }
+    }
+}
+
+struct Lock<'a, T> {
+    mutex: &'a FairMutex<T>,
+    // Whether this Lock has already pushed a Waker into the waiter queue. We only want one entry
+    // per Lock, even though poll might run more than once before we reach the front.
+    queued: bool,
+}
+
+impl<'a, T> Future for Lock<'a, T> {
+    type Output = FairMutexGuard<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if this.mutex.try_acquire(context.waker(), this.queued) {
+            return Poll::Ready(FairMutexGuard { mutex: this.mutex });
+        }
+        let mut waiters = this.mutex.waiters.lock().unwrap();
+        if this.queued {
+            // Replace our stale Waker with the current one.
+            if let Some(waker) = waiters.front_mut() {
+                *waker = context.waker().clone();
+            }
+        } else {
+            waiters.push_back(context.waker().clone());
+            this.queued = true;
+        }
+        Poll::Pending
+    }
+}
+
+struct FairMutexGuard<'a, T> {
+    mutex: &'a FairMutex<T>,
+}
+
+impl<'a, T> Deref for FairMutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<'a, T> DerefMut for FairMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<'a, T> Drop for FairMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.locked.store(false, Ordering::Release);
+        // Hand ownership to the next waiter in arrival order, if there is one.
+        if let Some(waker) = self.mutex.waiters.lock().unwrap().front() {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+static LOCK: std::sync::OnceLock<FairMutex<()>> = std::sync::OnceLock::new();
+
+fn lock() -> &'static FairMutex<()> {
+    LOCK.get_or_init(|| FairMutex::new(()))
+}
+
+async fn foo() {
+    let _guard = lock().lock().await;
+    tokio::time::sleep(Duration::from_millis(10)).await;
+}
+
+#[tokio::main]
+async fn main() {
+    let mut future1 = pin!(foo());
+    loop {
+        select! {
+            _ = &mut future1 => break,
+            _ = tokio::time::sleep(Duration::from_millis(5)) => {
+                println!("We make it here...");
+                foo().await;
+                println!("...but not here!");
+            }
+        }
+    }
+}
@@ -1,10 +1,11 @@
 use anyhow::Context;
 use pulldown_cmark::{
-    BrokenLink, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd,
+    Alignment, BrokenLink, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd,
 };
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 use syntect::easy::HighlightLines;
 use syntect::highlighting::{Style, ThemeSet};
 use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
@@ -46,7 +47,89 @@ struct CargoToml {
     package: CargoTomlPackage,
 }
 
-fn playground_url(url: Url, markdown_filepath: &Path) -> anyhow::Result<String> {
+// Settings a post can override by starting with a "---\n...\n---\n" TOML front-matter block.
+// Prose-heavy posts want typographic quotes and dashes; posts that are mostly inline code samples
+// often don't, since ASCII quotes and hyphens are what readers expect to copy-paste.
+#[derive(Debug, serde::Deserialize)]
+#[serde(default)]
+struct PostFrontMatter {
+    smart_punctuation: bool,
+}
+
+impl Default for PostFrontMatter {
+    fn default() -> Self {
+        PostFrontMatter {
+            smart_punctuation: true,
+        }
+    }
+}
+
+// Splits a leading "---\n...\n---\n" front-matter block off of a post, if it has one, and parses
+// it as TOML. Posts with no front matter get PostFrontMatter::default() and are returned
+// untouched.
+fn split_front_matter(markdown_input: &str) -> anyhow::Result<(PostFrontMatter, &str)> {
+    let Some(rest) = markdown_input.strip_prefix("---\n") else {
+        return Ok((PostFrontMatter::default(), markdown_input));
+    };
+    let Some(end) = rest.find("\n---\n") else {
+        anyhow::bail!(r#"front matter is missing its closing "---""#);
+    };
+    let front_matter = toml::from_str(&rest[..end])?;
+    Ok((front_matter, &rest[end + "\n---\n".len()..]))
+}
+
+// Recorded instead of bubbling up immediately, so that --check-code can check every embedded
+// sample across every post in one run and report them all together.
+struct CodeCheckFailure {
+    markdown_file: PathBuf,
+    source_path: PathBuf,
+    message: String,
+}
+
+// Runs `cargo check` (or `cargo test`, if the playground link asked for mode=test) against the
+// crate containing `rust_file`, the same crate whose Cargo.toml supplied `edition` above. Pushes
+// onto `failures` instead of returning an error, so one broken sample doesn't stop the rest of the
+// site from being checked.
+fn check_playground_code(
+    rust_file: &Path,
+    url: &Url,
+    markdown_filepath: &Path,
+    edition: &str,
+    failures: &mut Vec<CodeCheckFailure>,
+) -> anyhow::Result<()> {
+    let crate_dir = rust_file.parent().unwrap();
+    let subcommand = if url.query_pairs().any(|(k, v)| k == "mode" && v == "test") {
+        "test"
+    } else {
+        "check"
+    };
+    let output = Command::new("cargo")
+        .arg(subcommand)
+        .current_dir(crate_dir)
+        .output()
+        .context(format!(
+            "running cargo {subcommand} in {}",
+            crate_dir.to_string_lossy(),
+        ))?;
+    if !output.status.success() {
+        failures.push(CodeCheckFailure {
+            markdown_file: markdown_filepath.to_path_buf(),
+            source_path: rust_file.to_path_buf(),
+            message: format!(
+                "cargo {subcommand} failed (edition {edition}):\n{}",
+                String::from_utf8_lossy(&output.stderr),
+            ),
+        });
+    }
+    Ok(())
+}
+
+fn playground_url(
+    url: Url,
+    markdown_filepath: &Path,
+    check_code: bool,
+    failures: &mut Vec<CodeCheckFailure>,
+) -> anyhow::Result<String> {
     let rust_file = markdown_filepath
         .parent()
         .unwrap()
@@ -60,6 +143,9 @@ fn playground_url(url: Url, markdown_filepath: &Path) -> anyhow::Result<String>
         format!("reading file {}", cargo_toml_file.to_string_lossy()),
     )?)?;
     let edition = cargo_toml.package.edition;
+    if check_code {
+        check_playground_code(&rust_file, &url, markdown_filepath, &edition, failures)?;
+    }
     let mut ret = Url::parse("https://play.rust-lang.org")?;
     // Preserve supplied query parameters, for example mode=release.
     ret.set_query(url.query());
@@ -68,21 +154,75 @@ fn playground_url(url: Url, markdown_filepath: &Path) -> anyhow::Result<String>
     Ok(ret.into())
 }
 
+// Resolves post.md#section-style intra-site links to the post.html#slug anchor that chunk4-1's
+// heading-anchor pass assigned, the same way rustdoc's LinkReplacer resolves intra-doc links only
+// after a first collection pass has seen every page's headings. Returns None for anything that
+// isn't "a relative path ending in .md, optionally with a #fragment", so ordinary external and
+// playground links fall through to the handling below unchanged.
+fn resolve_internal_link(
+    url_str: &str,
+    markdown_filepath: &Path,
+    post_slugs: &HashMap<PathBuf, HashSet<String>>,
+) -> Option<String> {
+    let (path_part, fragment) = match url_str.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (url_str, None),
+    };
+    // An absolute URL parses on its own; a relative path fails with RelativeUrlWithoutBase.
+    if !path_part.ends_with(".md") || Url::parse(path_part).is_ok() {
+        return None;
+    }
+    let target_md = markdown_filepath.parent().unwrap().join(path_part);
+    let canonical_target = target_md
+        .canonicalize()
+        .unwrap_or_else(|e| panic!("broken internal link to {}: {e}", target_md.to_string_lossy()));
+    let slugs = post_slugs.get(&canonical_target).unwrap_or_else(|| {
+        panic!(
+            "broken internal link from {}: {} is not a rendered post",
+            markdown_filepath.to_string_lossy(),
+            target_md.to_string_lossy(),
+        )
+    });
+    if let Some(fragment) = fragment {
+        assert!(
+            slugs.contains(fragment),
+            "broken internal link from {}: {}#{} has no matching heading",
+            markdown_filepath.to_string_lossy(),
+            target_md.to_string_lossy(),
+            fragment,
+        );
+    }
+    let html_path = format!("{}.html", path_part.trim_end_matches(".md"));
+    Some(match fragment {
+        Some(fragment) => format!("{html_path}#{fragment}"),
+        None => html_path,
+    })
+}
+
 fn link_url_to_escaped_href(
     url_str: impl Into<String>,
     markdown_filepath: &Path,
+    check_code: bool,
+    failures: &mut Vec<CodeCheckFailure>,
+    post_slugs: &HashMap<PathBuf, HashSet<String>>,
 ) -> anyhow::Result<String> {
     let url_string = url_str.into();
-    let unescaped = match Url::parse(&url_string) {
-        Ok(parsed) => {
-            if parsed.scheme() == "playground" {
-                playground_url(parsed, markdown_filepath)?
-            } else {
-                url_string
+    let unescaped = if let Some(resolved) =
+        resolve_internal_link(&url_string, markdown_filepath, post_slugs)
+    {
+        resolved
+    } else {
+        match Url::parse(&url_string) {
+            Ok(parsed) => {
+                if parsed.scheme() == "playground" {
+                    playground_url(parsed, markdown_filepath, check_code, failures)?
+                } else {
+                    url_string
+                }
             }
+            Err(url::ParseError::RelativeUrlWithoutBase) => url_string,
+            Err(e) => panic!("bad URL: {e}"),
         }
-        Err(url::ParseError::RelativeUrlWithoutBase) => url_string,
-        Err(e) => panic!("bad URL: {e}"),
     };
     Ok(html_escape::encode_double_quoted_attribute(&unescaped).to_string())
 }
@@ -97,7 +237,131 @@ struct CodeBlock {
     contents_text: String,
 }
 
-struct Output {
+// Mirrors rustdoc's IdMap: turns heading text into a URL-safe slug, and disambiguates repeated
+// headings (e.g. two "Example" sections) by appending "-1", "-2", etc.
+struct IdMap {
+    // base slug -> how many times it's been used so far
+    seen: HashMap<String, usize>,
+}
+
+impl IdMap {
+    fn new() -> Self {
+        Self {
+            seen: HashMap::new(),
+        }
+    }
+
+    fn slugify(text: &str) -> String {
+        let mut slug = String::new();
+        let mut last_was_hyphen = true; // starts true so a leading run of punctuation is dropped
+        for c in text.chars() {
+            if c.is_alphanumeric() {
+                slug.extend(c.to_lowercase());
+                last_was_hyphen = false;
+            } else if !last_was_hyphen {
+                slug.push('-');
+                last_was_hyphen = true;
+            }
+        }
+        slug.trim_end_matches('-').to_string()
+    }
+
+    fn derive_id(&mut self, text: &str) -> String {
+        let base = Self::slugify(text);
+        let base = if base.is_empty() {
+            "section".to_string()
+        } else {
+            base
+        };
+        match self.seen.get_mut(&base) {
+            None => {
+                self.seen.insert(base.clone(), 0);
+                base
+            }
+            Some(count) => {
+                *count += 1;
+                format!("{base}-{count}")
+            }
+        }
+    }
+}
+
+// Buffers a table of contents as headings stream by, nesting <ul>s to match heading depth. Pushes
+// a new level when the heading level increases and pops back to the matching level (closing out
+// any deeper levels along the way) when it decreases or repeats.
+struct TocBuilder {
+    html: String,
+    // Heading levels (2..=5) with a currently-open <ul>, outermost first.
+    open_levels: Vec<u8>,
+}
+
+impl TocBuilder {
+    fn new() -> Self {
+        Self {
+            html: String::new(),
+            open_levels: Vec::new(),
+        }
+    }
+
+    fn push_heading(&mut self, level: u8, slug: &str, text_html: &str) {
+        while let Some(&top) = self.open_levels.last() {
+            if top < level {
+                break;
+            }
+            self.html += "</li>";
+            if top > level {
+                self.html += "</ul>";
+                self.open_levels.pop();
+            } else {
+                break; // top == level: stay in the same <ul>, just start a new sibling <li>
+            }
+        }
+        if self.open_levels.last() != Some(&level) {
+            self.html += "<ul>";
+            self.open_levels.push(level);
+        }
+        self.html += &format!(r#"<li><a href="#{slug}">{text_html}</a>"#);
+    }
+
+    fn finish(mut self) -> String {
+        while self.open_levels.pop().is_some() {
+            self.html += "</li></ul>";
+        }
+        self.html
+    }
+}
+
+fn heading_level_rank(level: HeadingLevel) -> u8 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+fn alignment_style_attr(alignment: Alignment) -> &'static str {
+    match alignment {
+        Alignment::None => "",
+        Alignment::Left => r#" style="text-align: left""#,
+        Alignment::Center => r#" style="text-align: center""#,
+        Alignment::Right => r#" style="text-align: right""#,
+    }
+}
+
+// Headings are buffered until TagEnd::Heading, because the anchor slug needs the heading's text,
+// and that text streams in as a sequence of Text events interleaved with Strong, Emphasis and the
+// like, just like the rest of the document. Only push_text adds to `text`: inline code spans
+// (Event::Code) go into `html` alone, so their words are left out of the slug.
+struct HeadingBuffer {
+    level: HeadingLevel,
+    html: String,
+    text: String,
+}
+
+struct Output<'a> {
     document_html: String,
     title_html: String,
     in_title: bool,
@@ -107,15 +371,41 @@ struct Output {
     current_footnote: Option<Footnote>,
     // Unfortunately code blocks are also parsed incrementally, which is kind of awkward.
     current_code_block: Option<CodeBlock>,
+    // Headings are parsed incrementally too, so their slug can be derived from all of their text
+    // (see HeadingBuffer for what that includes).
+    current_heading: Option<HeadingBuffer>,
+    // Set by Tag::Table and consulted by each TableCell, to pick the right text-align style.
+    table_alignments: Vec<Alignment>,
+    // TableHead cells render as <th>; TableRow cells render as <td>.
+    in_table_head: bool,
+    table_column: usize,
     // map of name to contents
     footnotes: HashMap<String, Footnote>,
     // sorted map of offset to name
     footnote_references: BTreeMap<usize, Vec<String>>,
+    id_map: IdMap,
+    toc_builder: TocBuilder,
+    // Every slug this post's headings were assigned, so the first render pass can report them
+    // back to main for cross-post link resolution.
+    heading_slugs: HashSet<String>,
     markdown_filepath: PathBuf,
+    check_code: bool,
+    code_check_failures: Vec<CodeCheckFailure>,
+    // Collected in a first pass over every post, consulted here to resolve post.md#section links.
+    post_slugs: &'a HashMap<PathBuf, HashSet<String>>,
+    // True during the first pass, when post_slugs is necessarily incomplete (most posts haven't
+    // been through this function yet). Link resolution is skipped entirely in that case, since the
+    // first pass's rendered HTML is thrown away -- only its heading_slugs are kept.
+    collect_slugs_only: bool,
 }
 
-impl Output {
-    fn new(markdown_filepath: impl Into<PathBuf>) -> Self {
+impl<'a> Output<'a> {
+    fn new(
+        markdown_filepath: impl Into<PathBuf>,
+        check_code: bool,
+        post_slugs: &'a HashMap<PathBuf, HashSet<String>>,
+        collect_slugs_only: bool,
+    ) -> Self {
         Self {
             document_html: String::new(),
             title_html: String::new(),
@@ -124,9 +414,20 @@ impl Output {
             in_subtitle: false,
             current_footnote: None,
             current_code_block: None,
+            current_heading: None,
+            table_alignments: Vec::new(),
+            in_table_head: false,
+            table_column: 0,
             footnotes: HashMap::new(),
             footnote_references: BTreeMap::new(),
+            id_map: IdMap::new(),
+            toc_builder: TocBuilder::new(),
+            heading_slugs: HashSet::new(),
             markdown_filepath: markdown_filepath.into(),
+            check_code,
+            code_check_failures: Vec::new(),
+            post_slugs,
+            collect_slugs_only,
         }
     }
 
@@ -134,6 +435,9 @@ impl Output {
         if let Some(code_block) = &mut self.current_code_block {
             code_block.contents_text += text;
         } else {
+            if let Some(heading) = &mut self.current_heading {
+                heading.text += text;
+            }
             self.push_html(&html_escape::encode_text(text));
         }
     }
@@ -149,6 +453,8 @@ impl Output {
         } else if self.in_subtitle {
             assert!(!self.in_title);
             self.subtitle_html += html;
+        } else if let Some(heading) = &mut self.current_heading {
+            heading.html += html;
         } else if let Some(footnote) = &mut self.current_footnote {
             footnote.contents_html += html;
         } else {
@@ -156,6 +462,33 @@ impl Output {
         }
     }
 
+    fn start_heading(&mut self, level: HeadingLevel) {
+        assert!(!self.in_title);
+        assert!(!self.in_subtitle);
+        assert!(self.current_heading.is_none(), "already in a heading");
+        assert!(self.current_code_block.is_none(), "already in a codeblock");
+        self.current_heading = Some(HeadingBuffer {
+            level,
+            html: String::new(),
+            text: String::new(),
+        });
+    }
+
+    fn finish_heading(&mut self) {
+        let Some(heading) = self.current_heading.take() else {
+            panic!("not in a heading");
+        };
+        let slug = self.id_map.derive_id(heading.text.trim());
+        self.toc_builder
+            .push_heading(heading_level_rank(heading.level), &slug, &heading.html);
+        self.heading_slugs.insert(slug.clone());
+        let level = heading.level;
+        self.document_html += &format!(
+            r#"<{level} id="{slug}"><a href="#{slug}">{}</a></{level}>"#,
+            heading.html,
+        );
+    }
+
     fn start_footnote(&mut self, name: String) {
         assert!(!self.in_title);
         assert!(!self.in_subtitle);
@@ -242,9 +575,23 @@ impl Output {
         }
 
         if let Some(code_link) = &code_lines.link {
+            // post_slugs is necessarily incomplete on the first pass (that's the pass
+            // building it), so cross-post links can't be resolved yet; this pass's HTML
+            // is discarded anyway, so a placeholder href is fine.
+            let href = if self.collect_slugs_only {
+                String::new()
+            } else {
+                link_url_to_escaped_href(
+                    &code_link.url,
+                    &self.markdown_filepath,
+                    self.check_code,
+                    &mut self.code_check_failures,
+                    self.post_slugs,
+                )?
+            };
             self.document_html += &format!(
                 r#"<div class="code_link"><a href="{}">{}</a></div>"#,
-                link_url_to_escaped_href(&code_link.url, &self.markdown_filepath)?,
+                href,
                 html_escape::encode_text(&code_link.text),
             );
         }
@@ -303,23 +650,38 @@ impl Output {
     }
 }
 
-fn render_markdown(markdown_filepath: impl AsRef<Path>) -> anyhow::Result<String> {
+fn render_markdown(
+    markdown_filepath: impl AsRef<Path>,
+    check_code: bool,
+    code_check_failures: &mut Vec<CodeCheckFailure>,
+    heading_slugs: &mut HashSet<String>,
+    post_slugs: &HashMap<PathBuf, HashSet<String>>,
+    collect_slugs_only: bool,
+) -> anyhow::Result<String> {
     let markdown_input = fs::read_to_string(markdown_filepath.as_ref()).context(format!(
         "reading markdown file: {}",
         markdown_filepath.as_ref().to_string_lossy(),
     ))?;
+    let (front_matter, markdown_body) = split_front_matter(&markdown_input)?;
     let mut options = Options::empty();
     options.insert(Options::ENABLE_STRIKETHROUGH);
     options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_TASKLISTS);
+    if front_matter.smart_punctuation {
+        // html_escape::encode_text only escapes '&', '<', and '>', so the curly quotes, em/en
+        // dashes, and ellipses this option emits as plain Event::Text pass through untouched.
+        options.insert(Options::ENABLE_SMART_PUNCTUATION);
+    }
     let parser = Parser::new_with_broken_link_callback(
-        &markdown_input,
+        markdown_body,
         options,
         Some(|link: BrokenLink| {
             panic!("broken link: \"{}\"", link.reference);
         }),
     );
 
-    let mut output = Output::new(markdown_filepath.as_ref());
+    let mut output = Output::new(markdown_filepath.as_ref(), check_code, post_slugs, collect_slugs_only);
 
     let mut nested_p_tag = false;
     let mut seen_link_ids = HashSet::new();
@@ -343,6 +705,10 @@ fn render_markdown(markdown_filepath: impl AsRef<Path>) -> anyhow::Result<String
             Event::FootnoteReference(s) => {
                 output.add_footnote_reference(s.to_string());
             }
+            Event::TaskListMarker(checked) => {
+                let checked_attr = if checked { " checked" } else { "" };
+                output.push_html(&format!(r#"<input type="checkbox" disabled{checked_attr}> "#));
+            }
             Event::Start(tag) => match tag {
                 Tag::BlockQuote(_) => {
                     output.push_html("\n\n<blockquote>");
@@ -362,7 +728,8 @@ fn render_markdown(markdown_filepath: impl AsRef<Path>) -> anyhow::Result<String
                     } else if level == HeadingLevel::H6 {
                         output.in_subtitle = true;
                     } else {
-                        output.push_html(&format!("\n</section>\n\n<section>\n<{level}>"));
+                        output.push_html("\n</section>\n\n<section>\n");
+                        output.start_heading(level);
                     }
                 }
                 Tag::Strong => output.push_html("<strong>"),
@@ -372,10 +739,22 @@ fn render_markdown(markdown_filepath: impl AsRef<Path>) -> anyhow::Result<String
                         seen_link_ids.insert(id);
                     }
                     assert!(!dest_url.is_empty());
-                    output.push_html(&format!(
-                        r#"<a class="custom-link-color" href="{}">"#,
-                        link_url_to_escaped_href(dest_url.as_ref(), markdown_filepath.as_ref())?,
-                    ));
+                    // post_slugs is necessarily incomplete on the first pass (that's the pass
+                    // building it), so cross-post links can't be resolved yet; this pass's HTML
+                    // is discarded anyway, so a placeholder href is fine.
+                    let href = if output.collect_slugs_only {
+                        String::new()
+                    } else {
+                        link_url_to_escaped_href(
+                            dest_url.as_ref(),
+                            markdown_filepath.as_ref(),
+                            output.check_code,
+                            &mut output.code_check_failures,
+                            output.post_slugs,
+                        )?
+                    };
+                    output
+                        .push_html(&format!(r#"<a class="custom-link-color" href="{href}">"#));
                 }
                 Tag::CodeBlock(kind) => {
                     let CodeBlockKind::Fenced(language) = kind else {
@@ -388,6 +767,32 @@ fn render_markdown(markdown_filepath: impl AsRef<Path>) -> anyhow::Result<String
                 Tag::FootnoteDefinition(s) => {
                     output.start_footnote(s.to_string());
                 }
+                Tag::Table(alignments) => {
+                    output.table_alignments = alignments;
+                    output.push_html("\n\n<table>");
+                }
+                Tag::TableHead => {
+                    output.in_table_head = true;
+                    output.table_column = 0;
+                    output.push_html("<thead><tr>");
+                }
+                Tag::TableRow => {
+                    output.table_column = 0;
+                    output.push_html("<tr>");
+                }
+                Tag::TableCell => {
+                    let alignment = output
+                        .table_alignments
+                        .get(output.table_column)
+                        .copied()
+                        .unwrap_or(Alignment::None);
+                    let style = alignment_style_attr(alignment);
+                    if output.in_table_head {
+                        output.push_html(&format!("<th{style}>"));
+                    } else {
+                        output.push_html(&format!("<td{style}>"));
+                    }
+                }
                 other => unimplemented!("{:?}", other),
             },
             Event::End(tag) => match tag {
@@ -399,7 +804,7 @@ fn render_markdown(markdown_filepath: impl AsRef<Path>) -> anyhow::Result<String
                     } else if level == HeadingLevel::H6 {
                         output.in_subtitle = false;
                     } else {
-                        output.push_html(&format!("</{}>", level));
+                        output.finish_heading();
                     }
                 }
                 TagEnd::Strong => output.push_html("</strong>"),
@@ -413,6 +818,16 @@ fn render_markdown(markdown_filepath: impl AsRef<Path>) -> anyhow::Result<String
                 TagEnd::FootnoteDefinition => {
                     output.finish_footnote();
                 }
+                TagEnd::Table => output.push_html("</tbody></table>"),
+                TagEnd::TableHead => {
+                    output.in_table_head = false;
+                    output.push_html("</tr></thead><tbody>");
+                }
+                TagEnd::TableRow => output.push_html("</tr>"),
+                TagEnd::TableCell => {
+                    output.push_html(if output.in_table_head { "</th>" } else { "</td>" });
+                    output.table_column += 1;
+                }
                 other => unimplemented!("{:?}", other),
             },
             other => unimplemented!("{:?}", other),
@@ -420,6 +835,8 @@ fn render_markdown(markdown_filepath: impl AsRef<Path>) -> anyhow::Result<String
     }
 
     output.validate_footnotes();
+    code_check_failures.append(&mut output.code_check_failures);
+    heading_slugs.extend(output.heading_slugs);
 
     let mut document_with_footnotes = String::new();
     let mut current_offset = 0;
@@ -452,6 +869,13 @@ fn render_markdown(markdown_filepath: impl AsRef<Path>) -> anyhow::Result<String
     }
     document_with_footnotes += &output.document_html[current_offset..];
 
+    // Posts opt into a table of contents by writing "\[TOC]" in the body. The backslash matters: a
+    // bare [TOC] is a reference link with no definition, and the broken-link callback above panics
+    // on it. The escaped form renders as the text "[TOC]", which is replaced here with the nested
+    // <ul> tree we built up heading by heading.
+    let toc_html = format!(r#"<nav class="toc">{}</nav>"#, output.toc_builder.finish());
+    let document_with_footnotes = document_with_footnotes.replace("[TOC]", &toc_html);
+
     Ok(HEADER
         .replace("__TITLE__", &output.title_html)
         .replace("__SUBTITLE__", &output.subtitle_html)
@@ -566,6 +990,10 @@ impl CodeLines {
 }
 
 fn main() -> anyhow::Result<()> {
+    // --check-code compiles every playground-linked sample with `cargo check` (or `cargo test`
+    // for mode=test links) as part of the render, so a broken sample fails the build instead of
+    // silently shipping a dead playground link.
+    let check_code = std::env::args().any(|arg| arg == "--check-code");
     let cargo_toml_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
     let posts_dir = cargo_toml_dir.join("../posts");
     let render_dir = cargo_toml_dir.join("../www");
@@ -573,17 +1001,67 @@ fn main() -> anyhow::Result<()> {
     for entry in fs::read_dir(posts_dir)? {
         post_paths.insert(entry?.path());
     }
+
+    // First pass: render every post once just to collect its heading slugs, keyed by canonical
+    // path so post.md#section links can be resolved regardless of how they're spelled relative to
+    // the linking post. This mirrors rustdoc's two-pass LinkReplacer: you can't resolve a link to
+    // another page's heading until that page has been through the same slug-assignment logic.
+    let mut post_slugs: HashMap<PathBuf, HashSet<String>> = HashMap::new();
+    for path in &post_paths {
+        if path.extension() != Some("md".as_ref()) {
+            continue;
+        }
+        let mut discarded_failures = Vec::new();
+        let mut heading_slugs = HashSet::new();
+        render_markdown(
+            path,
+            /* check_code */ false,
+            &mut discarded_failures,
+            &mut heading_slugs,
+            &HashMap::new(),
+            /* collect_slugs_only */ true,
+        )?;
+        let canonical_path = path
+            .canonicalize()
+            .context(format!("canonicalizing {}", path.to_string_lossy()))?;
+        post_slugs.insert(canonical_path, heading_slugs);
+    }
+
+    // Second pass: render for real, now that post_slugs lets internal links resolve.
+    let mut code_check_failures = Vec::new();
     for path in &post_paths {
         if path.extension() != Some("md".as_ref()) {
             continue;
         }
         let post_name = path.file_name().unwrap().to_string_lossy().to_string();
         println!("rendering {post_name}");
-        let post_html = render_markdown(path)?;
+        let mut heading_slugs = HashSet::new();
+        let post_html = render_markdown(
+            path,
+            check_code,
+            &mut code_check_failures,
+            &mut heading_slugs,
+            &post_slugs,
+            /* collect_slugs_only */ false,
+        )?;
         fs::write(
             render_dir.join(post_name.replace(".md", ".html")),
             &post_html,
         )?;
     }
+    if !code_check_failures.is_empty() {
+        for failure in &code_check_failures {
+            eprintln!(
+                "code sample {} (embedded in {}) failed to compile:\n{}",
+                failure.source_path.to_string_lossy(),
+                failure.markdown_file.to_string_lossy(),
+                failure.message,
+            );
+        }
+        anyhow::bail!(
+            "{} embedded code sample(s) failed to compile",
+            code_check_failures.len(),
+        );
+    }
     Ok(())
 }